@@ -0,0 +1,15 @@
+#![no_main]
+
+use iced_gemini::gemini::gemtext::Document;
+use libfuzzer_sys::fuzz_target;
+use url::Url;
+
+fuzz_target!(|doc: &str| {
+    let url = Url::parse("gemini://example.com/a/b").unwrap();
+    let parsed = Document::parse(&url, doc);
+
+    // serializing is idempotent after the first round trip
+    let serialized = parsed.to_gemtext();
+    let reparsed = Document::parse(&url, &serialized);
+    assert_eq!(reparsed.to_gemtext(), serialized);
+});
@@ -0,0 +1,14 @@
+#![no_main]
+
+use iced_gemini::gemini::gemtext::Line;
+use libfuzzer_sys::fuzz_target;
+use url::Url;
+
+fuzz_target!(|line: &str| {
+    let url = Url::parse("gemini://example.com/a/b").unwrap();
+    match Line::parse(&url, line) {
+        Ok(Line::Heading(level, _)) => assert!((1..=3).contains(&level)),
+        Ok(Line::Link(target, _)) if !line.contains(':') => assert_eq!(target.scheme(), "gemini"),
+        _ => {}
+    }
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use iced_gemini::gemini::response::Response;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Response::try_from(data);
+});
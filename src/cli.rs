@@ -0,0 +1,461 @@
+use std::{
+    io::{IsTerminal, Write},
+    path::PathBuf,
+};
+
+use iced_gemini::{
+    gemini::{
+        Status,
+        client::{Client, FetchOutcome},
+        dump::TextRenderer,
+        gemtext::Document,
+    },
+    net::identity::Identity,
+};
+use thiserror::Error;
+use url::Url;
+
+use crate::{config, ui::render::render_document};
+
+const USAGE: &str = "\
+usage: iced_gemini fetch [options] <url>
+       iced_gemini dump [--width <columns>] <url|file>
+       iced_gemini render [--width <pixels>] -o <file.png> <url|file>
+
+fetch: fetches <url>, writing the response header to stderr and the body to stdout.
+
+  --raw              write the body even if it isn't text and stdout is a terminal
+  --follow           follow redirects
+  --input <text>     send <text> as the request's query string
+  --identity <name>  present the client certificate in <config>/identities/<name>.pem
+  --cert             write the server's certificate to stderr as PEM
+
+dump: renders a page or a local gemtext file as wrapped plain text, with
+numbered link references.
+
+  --width <columns>  the column to wrap text at (default 80)
+
+render: draws a page or a local gemtext file as it's shown in the window, with
+the software renderer, and writes it to a PNG image as tall as the page.
+
+  --width <pixels>   the width of the image (default 800, at most 8192)
+  -o <file.png>      the image to write";
+
+#[derive(Error, Debug)]
+enum CliError {
+    #[error("unknown command `{0}`")]
+    UnknownCommand(String),
+    #[error("unknown option `{0}`")]
+    UnknownOption(String),
+    #[error("`{0}` needs an argument")]
+    MissingArgument(String),
+    #[error("missing url")]
+    MissingUrl,
+    #[error("invalid url: {0}")]
+    InvalidUrl(#[from] url::ParseError),
+    #[error("invalid width `{0}`")]
+    InvalidWidth(String),
+    #[error("missing output file, use -o <file.png>")]
+    MissingOutput,
+    #[error("no config directory to load identities from")]
+    NoConfigDir,
+}
+
+#[derive(Debug, Default)]
+struct FetchArgs {
+    url: Option<Url>,
+    raw: bool,
+    follow: bool,
+    input: Option<String>,
+    identity: Option<String>,
+    dump_cert: bool,
+}
+
+impl FetchArgs {
+    fn parse(args: &[String]) -> Result<Self, CliError> {
+        let mut fetch = FetchArgs::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| CliError::MissingArgument(arg.clone()))
+            };
+            match arg.as_str() {
+                "--raw" => fetch.raw = true,
+                "--follow" => fetch.follow = true,
+                "--cert" => fetch.dump_cert = true,
+                "--input" => fetch.input = Some(value()?),
+                "--identity" => fetch.identity = Some(value()?),
+                x if x.starts_with("--") => return Err(CliError::UnknownOption(x.to_string())),
+                x => fetch.url = Some(Url::parse(x)?),
+            }
+        }
+
+        if fetch.url.is_none() {
+            return Err(CliError::MissingUrl);
+        }
+        Ok(fetch)
+    }
+}
+
+#[derive(Debug)]
+struct DumpArgs {
+    target: String,
+    width: Option<usize>,
+}
+
+impl DumpArgs {
+    fn parse(args: &[String]) -> Result<Self, CliError> {
+        let mut target = None;
+        let mut width = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--width" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| CliError::MissingArgument(arg.clone()))?;
+                    width = Some(
+                        value
+                            .parse()
+                            .map_err(|_| CliError::InvalidWidth(value.clone()))?,
+                    );
+                }
+                x if x.starts_with("--") => return Err(CliError::UnknownOption(x.to_string())),
+                x => target = Some(x.to_string()),
+            }
+        }
+
+        Ok(Self {
+            target: target.ok_or(CliError::MissingUrl)?,
+            width,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct RenderArgs {
+    target: String,
+    width: u32,
+    output: PathBuf,
+}
+
+impl RenderArgs {
+    const DEFAULT_WIDTH: u32 = 800;
+    /// Wider images would take gigabytes for a long page.
+    const MAX_WIDTH: u32 = 8192;
+
+    fn parse(args: &[String]) -> Result<Self, CliError> {
+        let mut target = None;
+        let mut width = Self::DEFAULT_WIDTH;
+        let mut output = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| CliError::MissingArgument(arg.clone()))
+            };
+            match arg.as_str() {
+                "--width" => {
+                    let value = value()?;
+                    width = value
+                        .parse()
+                        .ok()
+                        .filter(|x| (1..=Self::MAX_WIDTH).contains(x))
+                        .ok_or(CliError::InvalidWidth(value))?;
+                }
+                "-o" | "--output" => output = Some(PathBuf::from(value()?)),
+                x if x.starts_with('-') => return Err(CliError::UnknownOption(x.to_string())),
+                x => target = Some(x.to_string()),
+            }
+        }
+
+        Ok(Self {
+            target: target.ok_or(CliError::MissingUrl)?,
+            width,
+            output: output.ok_or(CliError::MissingOutput)?,
+        })
+    }
+}
+
+#[derive(Debug)]
+enum Command {
+    Fetch(FetchArgs),
+    Dump(DumpArgs),
+    Render(RenderArgs),
+}
+
+/// Runs the command line interface, returning the process exit code:
+/// 0 for a success response, 1 for any other response or a network error,
+/// and 2 for a usage error.
+pub fn run(args: &[String]) -> i32 {
+    let result = match args.first().map(String::as_str) {
+        Some("fetch") => FetchArgs::parse(&args[1..]).map(Command::Fetch),
+        Some("dump") => DumpArgs::parse(&args[1..]).map(Command::Dump),
+        Some("render") => RenderArgs::parse(&args[1..]).map(Command::Render),
+        Some("-h" | "--help") => {
+            println!("{USAGE}");
+            return 0;
+        }
+        Some(x) => Err(CliError::UnknownCommand(x.to_string())),
+        None => Err(CliError::MissingUrl),
+    };
+
+    let command = match result {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("error: {e}\n\n{USAGE}");
+            return 2;
+        }
+    };
+
+    let result = match command {
+        Command::Fetch(args) => fetch(args),
+        Command::Dump(args) => dump(&args),
+        Command::Render(args) => render(&args),
+    };
+    match result {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            eprintln!("error: {e}");
+            1
+        }
+    }
+}
+
+/// Returns whether the server responded with a success status.
+fn fetch(args: FetchArgs) -> anyhow::Result<bool> {
+    let mut url = args.url.ok_or(CliError::MissingUrl)?;
+    if let Some(input) = &args.input {
+        url.set_query(Some(input));
+    }
+
+    let mut client = Client::new().with_max_redirects(if args.follow { 5 } else { 0 });
+    if let Some(name) = &args.identity {
+        let path = config::config_dir()
+            .ok_or(CliError::NoConfigDir)?
+            .join("identities")
+            .join(format!("{name}.pem"));
+        client = client.with_identity(Identity::from_pem_file(path)?);
+    }
+
+    let exchange = client.request(&url, None)?;
+    let response = exchange.response;
+
+    let mut stderr = std::io::stderr().lock();
+    writeln!(
+        stderr,
+        "{} {}",
+        response.status as u8,
+        response.ctx.as_deref().unwrap_or_default()
+    )?;
+    if args.dump_cert {
+        match &exchange.certificate {
+            Some(certificate) => write!(stderr, "{}", pem_encode(certificate))?,
+            None => writeln!(stderr, "(no certificate)")?,
+        }
+    }
+
+    if response.status != Status::Success {
+        return Ok(false);
+    }
+
+    let body = response.body.unwrap_or_default();
+    let is_text = response
+        .ctx
+        .as_deref()
+        .and_then(|x| x.parse::<mime::Mime>().ok())
+        .is_none_or(|x| x.type_() == mime::TEXT);
+
+    let mut stdout = std::io::stdout().lock();
+    if !is_text && !args.raw && stdout.is_terminal() {
+        anyhow::bail!("not writing a binary body to the terminal, use --raw or redirect stdout");
+    }
+    stdout.write_all(&body)?;
+    stdout.flush()?;
+
+    Ok(true)
+}
+
+/// Returns whether the page could be rendered.
+fn dump(args: &DumpArgs) -> anyhow::Result<bool> {
+    let Some(document) = load(&args.target, "as text")? else {
+        return Ok(false);
+    };
+
+    let mut renderer = TextRenderer::new();
+    if let Some(width) = args.width {
+        renderer = renderer.width(width);
+    }
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(renderer.render(&document).as_bytes())?;
+    stdout.flush()?;
+    Ok(true)
+}
+
+/// Returns whether the page could be rendered.
+fn render(args: &RenderArgs) -> anyhow::Result<bool> {
+    let Some(document) = load(&args.target, "as an image")? else {
+        return Ok(false);
+    };
+
+    let image = render_document(&document, args.width, &iced::Theme::Light)?;
+    image.save(&args.output)?;
+    Ok(true)
+}
+
+/// Fetches the page at `target`, or reads it from a local gemtext file.
+/// Returns `None` if the server responded with anything but a page, after
+/// writing the response to stderr. `how` says how the page would be shown,
+/// for the error when it can't be.
+fn load(target: &str, how: &str) -> anyhow::Result<Option<Document>> {
+    let document = match Url::parse(target) {
+        // a one letter scheme is a Windows drive, not a URL
+        Ok(url) if url.scheme().len() > 1 => match Client::new().fetch(&url, None)? {
+            FetchOutcome::Page(_, content) => content.document,
+            FetchOutcome::Unsupported(_, mime, _) => {
+                anyhow::bail!("can't render a {mime} page {how}")
+            }
+            FetchOutcome::Input { prompt, .. } => {
+                eprintln!("input expected: {}", prompt.unwrap_or_default());
+                return Ok(None);
+            }
+            FetchOutcome::Failure(_, status, ctx) => {
+                eprintln!("{} {}", status as u8, ctx.unwrap_or_default());
+                return Ok(None);
+            }
+        },
+        // anything else is a local gemtext file
+        _ => {
+            let path = std::fs::canonicalize(target)?;
+            let contents = std::fs::read_to_string(&path)?;
+            let url = Url::from_file_path(&path)
+                .map_err(|()| anyhow::anyhow!("can't make a url from {}", path.display()))?;
+            Document::parse(&url, &contents)
+        }
+    };
+    Ok(Some(document))
+}
+
+fn pem_encode(der: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(der.len() * 4 / 3 + 4);
+    for chunk in der.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    let mut pem = String::from("-----BEGIN CERTIFICATE-----\n");
+    for line in encoded.as_bytes().chunks(64) {
+        pem.push_str(str::from_utf8(line).expect("base64 is ascii"));
+        pem.push('\n');
+    }
+    pem.push_str("-----END CERTIFICATE-----\n");
+    pem
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse_fetch_args() -> anyhow::Result<()> {
+        let fetch = FetchArgs::parse(&args(
+            "--follow --input hello gemini://example.com/ --identity me --cert",
+        ))?;
+        assert_eq!(fetch.url, Some(Url::parse("gemini://example.com/")?));
+        assert!(fetch.follow && fetch.dump_cert && !fetch.raw);
+        assert_eq!(fetch.input.as_deref(), Some("hello"));
+        assert_eq!(fetch.identity.as_deref(), Some("me"));
+
+        assert!(matches!(
+            FetchArgs::parse(&args("--raw")),
+            Err(CliError::MissingUrl)
+        ));
+        assert!(matches!(
+            FetchArgs::parse(&args("gemini://example.com/ --input")),
+            Err(CliError::MissingArgument(_))
+        ));
+        assert!(matches!(
+            FetchArgs::parse(&args("gemini://example.com/ --verbose")),
+            Err(CliError::UnknownOption(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_dump_args() {
+        let dump = DumpArgs::parse(&args("--width 60 page.gmi")).unwrap();
+        assert_eq!(dump.target, "page.gmi");
+        assert_eq!(dump.width, Some(60));
+
+        assert!(matches!(
+            DumpArgs::parse(&args("--width wide page.gmi")),
+            Err(CliError::InvalidWidth(_))
+        ));
+        assert!(matches!(
+            DumpArgs::parse(&args("--width 60")),
+            Err(CliError::MissingUrl)
+        ));
+    }
+
+    #[test]
+    fn test_parse_render_args() {
+        let render = RenderArgs::parse(&args("gemini://example.com/ -o page.png")).unwrap();
+        assert_eq!(render.target, "gemini://example.com/");
+        assert_eq!(render.width, RenderArgs::DEFAULT_WIDTH);
+        assert_eq!(render.output, PathBuf::from("page.png"));
+
+        let render = RenderArgs::parse(&args("--width 400 page.gmi --output out.png")).unwrap();
+        assert_eq!(render.width, 400);
+
+        assert!(matches!(
+            RenderArgs::parse(&args("--width 0 -o page.png page.gmi")),
+            Err(CliError::InvalidWidth(_))
+        ));
+        let render = RenderArgs::parse(&args("--width 8192 -o page.png page.gmi")).unwrap();
+        assert_eq!(render.width, RenderArgs::MAX_WIDTH);
+        assert!(matches!(
+            RenderArgs::parse(&args("--width 8193 -o page.png page.gmi")),
+            Err(CliError::InvalidWidth(_))
+        ));
+        assert!(matches!(
+            RenderArgs::parse(&args("page.gmi")),
+            Err(CliError::MissingOutput)
+        ));
+        assert!(matches!(
+            RenderArgs::parse(&args("-o page.png")),
+            Err(CliError::MissingUrl)
+        ));
+        assert!(matches!(
+            RenderArgs::parse(&args("page.gmi -o")),
+            Err(CliError::MissingArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_pem_encode() {
+        assert_eq!(
+            pem_encode(b"hello world"),
+            "-----BEGIN CERTIFICATE-----\naGVsbG8gd29ybGQ=\n-----END CERTIFICATE-----\n"
+        );
+        assert!(pem_encode(&[0; 48]).contains(&format!("\n{}\n", "A".repeat(64))));
+    }
+}
@@ -0,0 +1,141 @@
+use std::{
+    fmt,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+// how many numbered names `write_new` tries before giving up
+const WRITE_NEW_ATTEMPTS: usize = 1000;
+
+/// Directory holding the user's configuration files, following the XDG
+/// convention (`$XDG_CONFIG_HOME/iced_gemini`, or `~/.config/iced_gemini`).
+pub fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("iced_gemini"))
+}
+
+/// Directory saved files go in: `$XDG_DOWNLOAD_DIR`, or `~/Downloads`.
+pub fn download_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_DOWNLOAD_DIR")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Downloads")))
+}
+
+/// Writes `data` into a new file in `dir` named `stem.extension`, adding a
+/// number to the stem if that's taken. Never overwrites an existing file, and
+/// fails if too many of the numbered names are taken too.
+pub fn write_new(
+    dir: &Path,
+    stem: &str,
+    extension: Option<&str>,
+    data: &[u8],
+) -> io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    for i in 0..WRITE_NEW_ATTEMPTS {
+        let stem = if i == 0 {
+            stem.to_string()
+        } else {
+            format!("{stem} ({i})")
+        };
+        let name = match extension {
+            Some(extension) => format!("{stem}.{extension}"),
+            None => stem,
+        };
+        let path = dir.join(name);
+        match std::fs::File::create_new(&path) {
+            Ok(mut file) => {
+                file.write_all(data)?;
+                return Ok(path);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("{WRITE_NEW_ATTEMPTS} files named after `{stem}` already exist"),
+    ))
+}
+
+/// Why a line of a `ConfigFile` couldn't be parsed.
+#[derive(Error, Debug)]
+pub enum LineError {
+    #[error("line {0}: expected {1}")]
+    MalformedLine(usize, &'static str), // line number, the expected format
+    #[error("line {0}: invalid URL: {1}")]
+    InvalidUrl(usize, url::ParseError),
+}
+
+/// A file in the config directory holding one entry per line, in which
+/// blank lines and `#` comments are ignored.
+pub trait ConfigFile: Default + fmt::Display {
+    /// The file's name in the config directory.
+    const NAME: &'static str;
+
+    type Error: fmt::Display;
+
+    /// Adds what's on `line`, which is trimmed and neither blank nor a
+    /// comment.
+    fn parse_line(&mut self, line_number: usize, line: &str) -> Result<(), Self::Error>;
+
+    fn path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join(Self::NAME))
+    }
+
+    /// Parses the file, skipping and returning the errors for the lines that
+    /// can't be parsed so that the rest are still kept.
+    fn parse(s: &str) -> (Self, Vec<Self::Error>) {
+        let mut parsed = Self::default();
+        let mut errors = Vec::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Err(e) = parsed.parse_line(i + 1, line) {
+                errors.push(e);
+            }
+        }
+        (parsed, errors)
+    }
+
+    /// The saved file, or the default if it can't be read. Lines that can't
+    /// be parsed are skipped, and dropped the next time it's saved.
+    fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(x) => x,
+            Err(e) => {
+                log::info!("ConfigFile: not loading {}: {e}", path.display());
+                return Self::default();
+            }
+        };
+
+        let (parsed, errors) = Self::parse(&contents);
+        for e in errors {
+            log::warn!("ConfigFile: skipping in {}: {e}", path.display());
+        }
+        parsed
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let path = Self::path().ok_or(anyhow::anyhow!("no config directory"))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, self.to_string())?;
+        Ok(())
+    }
+}
+
+/// Parses `s` as a URL, for a `ConfigFile`'s line `line_number`.
+pub fn parse_url(line_number: usize, s: &str) -> Result<url::Url, LineError> {
+    url::Url::parse(s).map_err(|e| LineError::InvalidUrl(line_number, e))
+}
@@ -0,0 +1,124 @@
+use std::path::PathBuf;
+
+use iced_gemini::gemini::{
+    client::{Client, FetchOutcome},
+    export::{self, Epub},
+    gemtext::Document,
+};
+use url::Url;
+
+use crate::{config, feeds};
+
+/// The most linked pages bundled into one book.
+pub const MAX_LINKED_PAGES: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Html,
+    Markdown,
+    Epub,
+    EpubWithLinks, // the page and the pages it links to on the same capsule
+}
+
+impl Format {
+    pub const ALL: [Format; 4] = [
+        Format::Html,
+        Format::Markdown,
+        Format::Epub,
+        Format::EpubWithLinks,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Format::Html => "HTML",
+            Format::Markdown => "Markdown",
+            Format::Epub => "EPUB (this page)",
+            Format::EpubWithLinks => "EPUB (with linked pages)",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Html => "html",
+            Format::Markdown => "md",
+            Format::Epub | Format::EpubWithLinks => "epub",
+        }
+    }
+}
+
+/// Exports `doc` into a new file in the download directory, returning its
+/// path. For a book with linked pages, the pages are fetched first, so this
+/// can block for a while.
+pub fn export(client: &Client, doc: &Document, format: Format) -> anyhow::Result<PathBuf> {
+    let dir = config::download_dir().ok_or(anyhow::anyhow!("no download directory"))?;
+    let data = match format {
+        Format::Html => export::to_html(doc).into_bytes(),
+        Format::Markdown => export::to_markdown(doc).into_bytes(),
+        Format::Epub => book(doc, Vec::new()).to_bytes()?,
+        Format::EpubWithLinks => book(doc, fetch_linked(client, doc)).to_bytes()?,
+    };
+    let path = config::write_new(&dir, &file_stem(&doc.url), Some(format.extension()), &data)?;
+    Ok(path)
+}
+
+fn book(doc: &Document, linked: Vec<Document>) -> Epub {
+    linked.into_iter().fold(
+        Epub::new(&export::title(doc), &feeds::today()).chapter(doc.clone()),
+        Epub::chapter,
+    )
+}
+
+/// Fetches the pages `doc` links to on its capsule, one at a time, leaving
+/// out any that fail or aren't pages.
+fn fetch_linked(client: &Client, doc: &Document) -> Vec<Document> {
+    let mut pages = Vec::new();
+    let links = export::capsule_links(doc);
+    if links.len() > MAX_LINKED_PAGES {
+        log::info!(
+            "export: only bundling the first {MAX_LINKED_PAGES} of {} linked pages",
+            links.len()
+        );
+    }
+    for url in links.into_iter().take(MAX_LINKED_PAGES) {
+        std::thread::sleep(feeds::HOST_INTERVAL);
+        match client.fetch(&url, None) {
+            // keep the linked URL, even if the page has moved, so links to it
+            // lead to its chapter
+            Ok(FetchOutcome::Page(_, content)) => {
+                pages.push(Document::from_lines(&url, content.document.lines));
+            }
+            Ok(_) => log::info!("export: leaving out {url}: not a page"),
+            Err(e) => log::warn!("export: leaving out {url}: {e}"),
+        }
+    }
+    pages
+}
+
+/// The name of a page's file, without its extension: the last segment of its
+/// path, or else its host.
+fn file_stem(url: &Url) -> String {
+    let segment = url
+        .path_segments()
+        .and_then(|mut x| x.next_back())
+        .filter(|x| !x.is_empty());
+    match segment {
+        Some(x) => x.rsplit_once('.').map_or(x, |(stem, _)| stem).to_string(),
+        None => url.host_str().unwrap_or("page").to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_stem() -> anyhow::Result<()> {
+        let stem = |url| Url::parse(url).map(|x| file_stem(&x));
+        assert_eq!(stem("gemini://example.com/walks/coast.gmi")?, "coast");
+        assert_eq!(stem("gemini://example.com/walks/")?, "example.com");
+        assert_eq!(stem("gemini://example.com")?, "example.com");
+        assert_eq!(stem("gemini://example.com/v1.2.notes")?, "v1.2");
+        assert_eq!(stem("about:feeds")?, "page");
+        Ok(())
+    }
+}
@@ -0,0 +1,433 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use iced_gemini::gemini::{
+    Status,
+    client::Client,
+    feed::{Feed, FeedError},
+    gemtext::{Document, Line},
+};
+use url::Url;
+
+use crate::config::{self, ConfigFile, LineError};
+
+/// How many of a new subscription's entries start unread; the rest are
+/// marked read, so subscribing to a long-running gemlog doesn't bury
+/// everything else.
+const UNREAD_ON_SUBSCRIBE: usize = 5;
+/// How long to wait between requests to the same host.
+pub const HOST_INTERVAL: Duration = Duration::from_secs(2);
+/// The longest we'll wait when a server asks us to slow down.
+const MAX_SLOW_DOWN: Duration = Duration::from_mins(1);
+
+// what the lines of `feeds` look like
+const FORMAT: &str =
+    "`subscribe <url> [title]` or `entry <feed> <url> <date> <read|unread> [title]`";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subscription {
+    pub url: Url,
+    pub title: Option<String>, // from the feed, once it's been fetched
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedEntry {
+    pub feed: Url,
+    pub url: Url,
+    pub date: String, // YYYY-MM-DD
+    pub title: String,
+    pub read: bool,
+}
+
+/// Subscribed feeds and the entries seen in them, stored in `feeds` in the
+/// config directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Feeds {
+    subscriptions: Vec<Subscription>,
+    entries: Vec<FeedEntry>,
+}
+
+impl ConfigFile for Feeds {
+    const NAME: &'static str = "feeds";
+
+    type Error = LineError;
+
+    fn parse_line(&mut self, line_number: usize, line: &str) -> Result<(), LineError> {
+        let malformed = || LineError::MalformedLine(line_number, FORMAT);
+        let url = |x| config::parse_url(line_number, x);
+        let (kind, rest) = line.split_once(' ').ok_or_else(malformed)?;
+        match kind {
+            "subscribe" => {
+                let (feed, title) = rest.split_once(' ').unwrap_or((rest, ""));
+                self.subscriptions.push(Subscription {
+                    url: url(feed)?,
+                    title: Some(title.trim().to_string()).filter(|x| !x.is_empty()),
+                });
+            }
+            "entry" => {
+                let mut fields = rest.splitn(5, ' ');
+                let mut field = || fields.next().ok_or_else(malformed);
+                let (feed, entry, date, read) = (field()?, field()?, field()?, field()?);
+                let title = fields.next().unwrap_or_default();
+                self.entries.push(FeedEntry {
+                    feed: url(feed)?,
+                    url: url(entry)?,
+                    date: date.to_string(),
+                    title: title.trim().to_string(),
+                    read: match read {
+                        "read" => true,
+                        "unread" => false,
+                        _ => return Err(malformed()),
+                    },
+                });
+            }
+            _ => return Err(malformed()),
+        }
+        Ok(())
+    }
+}
+
+impl Feeds {
+    pub fn is_subscribed(&self, url: &Url) -> bool {
+        self.subscriptions.iter().any(|x| x.url == *url)
+    }
+
+    pub fn subscribe(&mut self, url: Url) {
+        if !self.is_subscribed(&url) {
+            self.subscriptions.push(Subscription { url, title: None });
+        }
+    }
+
+    /// Also forgets the feed's entries.
+    pub fn unsubscribe(&mut self, url: &Url) {
+        self.subscriptions.retain(|x| x.url != *url);
+        self.entries.retain(|x| x.feed != *url);
+    }
+
+    pub fn urls(&self) -> impl Iterator<Item = &Url> {
+        self.subscriptions.iter().map(|x| &x.url)
+    }
+
+    /// Adds the entries of `feed`, fetched from `url`, that haven't been seen
+    /// before. Entries without a date are dated `today`. Returns how many
+    /// were added.
+    pub fn merge(&mut self, url: &Url, feed: &Feed, today: &str) -> usize {
+        let Some(subscription) = self.subscriptions.iter_mut().find(|x| x.url == *url) else {
+            return 0;
+        };
+        subscription.title = feed.title.as_deref().map(single_line);
+
+        let first_fetch = !self.entries.iter().any(|x| x.feed == *url);
+        let mut seen: HashSet<Url> = self.entries.iter().map(|x| x.url.clone()).collect();
+        let mut new: Vec<FeedEntry> = feed
+            .entries
+            .iter()
+            .filter_map(|entry| {
+                let entry_url = entry.url.as_ref().filter(|x| seen.insert((*x).clone()))?;
+                Some(FeedEntry {
+                    feed: url.clone(),
+                    url: entry_url.clone(),
+                    date: entry.date.clone().unwrap_or_else(|| today.to_string()),
+                    title: single_line(&entry.title),
+                    read: false,
+                })
+            })
+            .collect();
+
+        if first_fetch {
+            new.sort_by(|a, b| b.date.cmp(&a.date));
+            for entry in new.iter_mut().skip(UNREAD_ON_SUBSCRIBE) {
+                entry.read = true;
+            }
+        }
+        let added = new.len();
+        self.entries.extend(new);
+        added
+    }
+
+    /// Returns whether `url` was an unread entry.
+    pub fn mark_read(&mut self, url: &Url) -> bool {
+        let mut changed = false;
+        for entry in self.entries.iter_mut().filter(|x| x.url == *url && !x.read) {
+            entry.read = true;
+            changed = true;
+        }
+        changed
+    }
+
+    pub fn mark_all_read(&mut self) {
+        for entry in &mut self.entries {
+            entry.read = true;
+        }
+    }
+
+    /// The unread entries of every feed, newest first.
+    pub fn unread(&self) -> Vec<&FeedEntry> {
+        let mut unread: Vec<&FeedEntry> = self.entries.iter().filter(|x| !x.read).collect();
+        unread.sort_by(|a, b| b.date.cmp(&a.date));
+        unread
+    }
+
+    /// The `about:feeds` page: the unread entries, then the subscriptions.
+    /// `status` says how the last refresh went.
+    pub fn to_document(&self, status: &str) -> Document {
+        let url = |x: &str| Url::parse(x).expect("about: URLs are valid");
+        let titles: BTreeMap<&Url, &str> = self
+            .subscriptions
+            .iter()
+            .filter_map(|x| Some((&x.url, x.title.as_deref()?)))
+            .collect();
+
+        let mut lines = vec![
+            Line::Heading(1, "Feeds".into()),
+            Line::Text(status.to_string()),
+            Line::Link(url("about:feeds?refresh"), Some("Refresh".into())),
+            Line::Link(url("about:feeds?read-all"), Some("Mark all as read".into())),
+            Line::Text(String::new()),
+            Line::Heading(2, "Unread".into()),
+        ];
+        let unread = self.unread();
+        if unread.is_empty() {
+            lines.push(Line::Text("Nothing new.".into()));
+        }
+        for entry in unread {
+            let label = match titles.get(&entry.feed) {
+                Some(feed) => format!("{} {feed}: {}", entry.date, entry.title),
+                None => format!("{} {}", entry.date, entry.title),
+            };
+            lines.push(Line::Link(entry.url.clone(), Some(label)));
+        }
+
+        lines.push(Line::Text(String::new()));
+        lines.push(Line::Heading(2, "Subscriptions".into()));
+        if self.subscriptions.is_empty() {
+            lines.push(Line::Text(
+                "None yet: open a gemlog or an Atom or RSS feed and press ⭐ to subscribe.".into(),
+            ));
+        }
+        for subscription in &self.subscriptions {
+            lines.push(Line::Link(
+                subscription.url.clone(),
+                subscription.title.clone(),
+            ));
+        }
+        Document::from_lines(&url("about:feeds"), lines)
+    }
+}
+
+impl fmt::Display for Feeds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for subscription in &self.subscriptions {
+            let title = subscription.title.as_deref().unwrap_or_default();
+            writeln!(f, "subscribe {} {title}", subscription.url)?;
+        }
+        for entry in &self.entries {
+            let read = if entry.read { "read" } else { "unread" };
+            writeln!(
+                f,
+                "entry {} {} {} {read} {}",
+                entry.feed, entry.url, entry.date, entry.title
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Titles are stored one per line.
+fn single_line(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Groups `urls` by host, so each host can be sent one request at a time.
+pub fn by_host<'a>(urls: impl IntoIterator<Item = &'a Url>) -> Vec<Vec<Url>> {
+    let mut hosts: BTreeMap<Option<&str>, Vec<Url>> = BTreeMap::new();
+    for url in urls {
+        let group = hosts.entry(url.host_str()).or_default();
+        if !group.contains(url) {
+            group.push(url.clone());
+        }
+    }
+    hosts.into_values().collect()
+}
+
+/// Fetches the feeds at `urls`, which should all be on one host, one at a
+/// time. Waits between requests, and for as long as the server asks to if it
+/// says to slow down, blocking the thread it's called on throughout.
+pub fn fetch_all(client: &Client, urls: &[Url]) -> Vec<(Url, Result<Feed, String>)> {
+    let mut results = Vec::new();
+    for (i, url) in urls.iter().enumerate() {
+        if i > 0 {
+            std::thread::sleep(HOST_INTERVAL);
+        }
+        let mut result = Feed::fetch(client, url);
+        if let Some(wait) = slow_down(&result) {
+            log::info!("Feeds: slowing down for {wait:?} before retrying {url}");
+            std::thread::sleep(wait);
+            result = Feed::fetch(client, url);
+        }
+        results.push((url.clone(), result.map_err(|e| e.to_string())));
+    }
+    results
+}
+
+/// How long the server asked us to wait, if it said to slow down.
+fn slow_down(result: &anyhow::Result<Feed>) -> Option<Duration> {
+    let Err(e) = result else {
+        return None;
+    };
+    match e.downcast_ref::<FeedError>()? {
+        FeedError::Status(Status::SlowDown, meta) => Some(slow_down_wait(meta.as_deref())),
+        _ => None,
+    }
+}
+
+/// How long to wait after a slow down response with meta `meta`, which
+/// should be a number of seconds.
+pub fn slow_down_wait(meta: Option<&str>) -> Duration {
+    let seconds = meta.and_then(|x| x.trim().parse().ok());
+    seconds
+        .map_or(HOST_INTERVAL, Duration::from_secs)
+        .min(MAX_SLOW_DOWN)
+}
+
+/// Today's date as `YYYY-MM-DD`, in UTC.
+pub fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs() / 86400);
+    civil_date(days)
+}
+
+/// The date `days` after 1970-01-01, from Howard Hinnant's `civil_from_days`.
+fn civil_date(days: u64) -> String {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use iced_gemini::gemini::feed::Entry;
+
+    use super::*;
+
+    fn feed(entries: &[(&str, &str)]) -> Feed {
+        Feed {
+            title: Some("Example\ngemlog".into()),
+            subtitle: None,
+            entries: entries
+                .iter()
+                .map(|(url, date)| Entry {
+                    title: format!("Post {url}"),
+                    url: Some(Url::parse(url).unwrap()),
+                    date: Some(date.to_string()).filter(|x| !x.is_empty()),
+                    summary: None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_merge() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/gemlog/")?;
+        let mut feeds = Feeds::default();
+        feeds.subscribe(url.clone());
+
+        // only the newest few entries of a new subscription are unread
+        let entries: Vec<(String, String)> = (1..=7)
+            .map(|i| {
+                (
+                    format!("gemini://example.com/{i}.gmi"),
+                    format!("2024-01-0{i}"),
+                )
+            })
+            .collect();
+        let entries: Vec<(&str, &str)> = entries
+            .iter()
+            .map(|(a, b)| (a.as_str(), b.as_str()))
+            .collect();
+        assert_eq!(feeds.merge(&url, &feed(&entries), "2024-02-01"), 7);
+        let unread = feeds.unread();
+        assert_eq!(unread.len(), UNREAD_ON_SUBSCRIBE);
+        assert_eq!(unread[0].url.as_str(), "gemini://example.com/7.gmi");
+
+        // entries seen before are left alone, new ones are unread
+        let more = feed(&[
+            ("gemini://example.com/8.gmi", ""),
+            ("gemini://example.com/7.gmi", "2024-01-07"),
+        ]);
+        assert_eq!(feeds.merge(&url, &more, "2024-02-01"), 1);
+        assert_eq!(feeds.unread()[0].date, "2024-02-01");
+        assert_eq!(
+            feeds.subscriptions[0].title.as_deref(),
+            Some("Example gemlog")
+        );
+
+        assert!(feeds.mark_read(&Url::parse("gemini://example.com/8.gmi")?));
+        assert!(!feeds.mark_read(&Url::parse("gemini://example.com/8.gmi")?));
+        feeds.mark_all_read();
+        assert!(feeds.unread().is_empty());
+
+        // feeds we aren't subscribed to are ignored
+        let other = Url::parse("gemini://other.example/")?;
+        assert_eq!(feeds.merge(&other, &more, "2024-02-01"), 0);
+        feeds.unsubscribe(&url);
+        assert_eq!(feeds, Feeds::default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/gemlog/")?;
+        let mut feeds = Feeds::default();
+        feeds.subscribe(url.clone());
+        feeds.subscribe(Url::parse("gemini://other.example/atom.xml")?);
+        feeds.merge(
+            &url,
+            &feed(&[("gemini://example.com/1.gmi", "2024-01-01")]),
+            "",
+        );
+        let (parsed, errors) = Feeds::parse(&feeds.to_string());
+        assert!(errors.is_empty());
+        assert_eq!(parsed, feeds);
+        assert_eq!(by_host(feeds.urls()).len(), 2);
+
+        // bad lines are skipped, keeping the rest
+        let (parsed, errors) = Feeds::parse(
+            "# comment\n\
+             subscribe\n\
+             entry gemini://a/ gemini://a/b 2024-01-01 maybe\n\
+             subscribe not-a-url\n\
+             subscribe gemini://example.com/gemlog/\n",
+        );
+        assert!(matches!(
+            errors[..],
+            [
+                LineError::MalformedLine(2, _),
+                LineError::MalformedLine(3, _),
+                LineError::InvalidUrl(4, _),
+            ]
+        ));
+        assert!(parsed.is_subscribed(&url));
+        assert_eq!(parsed.urls().count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_civil_date() {
+        assert_eq!(civil_date(0), "1970-01-01");
+        assert_eq!(civil_date(19_782), "2024-02-29");
+        assert_eq!(civil_date(20_000), "2024-10-04");
+    }
+}
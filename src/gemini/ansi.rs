@@ -0,0 +1,271 @@
+//! ANSI escape sequences, as found in terminal output and colour art pasted
+//! into preformatted blocks. SGR sequences (`ESC [ ... m`) are parsed into
+//! styles, every other escape sequence is dropped.
+
+/// A colour from an SGR sequence, either from the 256 colour palette (the
+/// first 16 being the standard and bright colours) or 24 bit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnsiColor {
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl AnsiColor {
+    /// The colour as RGB, using xterm's default palette for indexed colours.
+    pub fn to_rgb(self) -> (u8, u8, u8) {
+        const STANDARD: [(u8, u8, u8); 16] = [
+            (0, 0, 0),
+            (205, 0, 0),
+            (0, 205, 0),
+            (205, 205, 0),
+            (0, 0, 238),
+            (205, 0, 205),
+            (0, 205, 205),
+            (229, 229, 229),
+            (127, 127, 127),
+            (255, 0, 0),
+            (0, 255, 0),
+            (255, 255, 0),
+            (92, 92, 255),
+            (255, 0, 255),
+            (0, 255, 255),
+            (255, 255, 255),
+        ];
+        const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+        match self {
+            AnsiColor::Rgb(r, g, b) => (r, g, b),
+            AnsiColor::Indexed(i @ 0..=15) => STANDARD[i as usize],
+            AnsiColor::Indexed(i @ 16..=231) => {
+                let i = i - 16;
+                (
+                    CUBE[(i / 36) as usize],
+                    CUBE[(i / 6 % 6) as usize],
+                    CUBE[(i % 6) as usize],
+                )
+            }
+            AnsiColor::Indexed(i) => {
+                let level = 8 + (i - 232) * 10;
+                (level, level, level)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AnsiStyle {
+    pub foreground: Option<AnsiColor>,
+    pub background: Option<AnsiColor>,
+    pub bold: bool,
+    pub underline: bool,
+}
+
+impl AnsiStyle {
+    /// Applies the parameters of one SGR sequence.
+    fn apply(&mut self, params: &str) {
+        let mut params = params.split(';').map(|x| {
+            if x.is_empty() {
+                Some(0)
+            } else {
+                x.parse::<u8>().ok()
+            }
+        });
+
+        while let Some(param) = params.next() {
+            match param {
+                Some(0) => *self = AnsiStyle::default(),
+                Some(1) => self.bold = true,
+                Some(22) => self.bold = false,
+                Some(4) => self.underline = true,
+                Some(24) => self.underline = false,
+                Some(x @ 30..=37) => self.foreground = Some(AnsiColor::Indexed(x - 30)),
+                Some(x @ 90..=97) => self.foreground = Some(AnsiColor::Indexed(x - 90 + 8)),
+                Some(39) => self.foreground = None,
+                Some(x @ 40..=47) => self.background = Some(AnsiColor::Indexed(x - 40)),
+                Some(x @ 100..=107) => self.background = Some(AnsiColor::Indexed(x - 100 + 8)),
+                Some(49) => self.background = None,
+                Some(38) => self.foreground = extended_color(&mut params),
+                Some(48) => self.background = extended_color(&mut params),
+                // italics, blinking etc. aren't supported
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Reads the rest of a `38;5;<n>` or `38;2;<r>;<g>;<b>` parameter list.
+fn extended_color(params: &mut impl Iterator<Item = Option<u8>>) -> Option<AnsiColor> {
+    match params.next()? {
+        Some(5) => Some(AnsiColor::Indexed(params.next()??)),
+        Some(2) => Some(AnsiColor::Rgb(
+            params.next()??,
+            params.next()??,
+            params.next()??,
+        )),
+        _ => None,
+    }
+}
+
+/// A run of text sharing one style.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnsiSpan {
+    pub text: String,
+    pub style: AnsiStyle,
+}
+
+pub fn has_escapes(s: &str) -> bool {
+    s.contains('\x1b')
+}
+
+/// Splits `s` into styled runs, dropping the escape sequences themselves.
+pub fn parse(s: &str) -> Vec<AnsiSpan> {
+    let mut spans: Vec<AnsiSpan> = Vec::new();
+    let mut style = AnsiStyle::default();
+    let mut rest = s;
+
+    while let Some(start) = rest.find('\x1b') {
+        push(&mut spans, &rest[..start], style);
+        let (sgr, after) = escape_sequence(&rest[start + 1..]);
+        if let Some(params) = sgr {
+            style.apply(params);
+        }
+        rest = after;
+    }
+    push(&mut spans, rest, style);
+    spans
+}
+
+/// Removes every escape sequence from `s`.
+pub fn strip(s: &str) -> String {
+    parse(s).into_iter().map(|span| span.text).collect()
+}
+
+fn push(spans: &mut Vec<AnsiSpan>, text: &str, style: AnsiStyle) {
+    if text.is_empty() {
+        return;
+    }
+    match spans.last_mut() {
+        Some(last) if last.style == style => last.text.push_str(text),
+        _ => spans.push(AnsiSpan {
+            text: text.to_string(),
+            style,
+        }),
+    }
+}
+
+/// Splits the escape sequence at the start of `s` (just after the `ESC`)
+/// from the text following it. Returns the parameters if it's an SGR
+/// sequence.
+fn escape_sequence(s: &str) -> (Option<&str>, &str) {
+    match s.chars().next() {
+        // CSI: parameter and intermediate bytes, ended by a byte in `@`..=`~`
+        Some('[') => {
+            let body = &s[1..];
+            match body.find(|c: char| ('@'..='~').contains(&c)) {
+                Some(end) => {
+                    let params = &body[..end];
+                    let sgr = (body[end..].starts_with('m')).then_some(params);
+                    (sgr, &body[end + 1..])
+                }
+                None => (None, ""),
+            }
+        }
+        // OSC: ended by BEL or `ESC \`
+        Some(']') => {
+            let body = &s[1..];
+            match body.find(['\x07', '\x1b']) {
+                Some(end) if body[end..].starts_with('\x07') => (None, &body[end + 1..]),
+                Some(end) => (
+                    None,
+                    body[end + 1..]
+                        .strip_prefix('\\')
+                        .unwrap_or(&body[end + 1..]),
+                ),
+                None => (None, ""),
+            }
+        }
+        // any other escape is two characters long
+        Some(c) => (None, &s[c.len_utf8()..]),
+        None => (None, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn style(foreground: Option<AnsiColor>, bold: bool) -> AnsiStyle {
+        AnsiStyle {
+            foreground,
+            bold,
+            ..AnsiStyle::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_sgr() {
+        let spans = parse("plain \x1b[1;31mbold red\x1b[22m red\x1b[0m \x1b[4munderline\x1b[m");
+        assert_eq!(
+            spans,
+            [
+                AnsiSpan {
+                    text: "plain ".into(),
+                    style: AnsiStyle::default(),
+                },
+                AnsiSpan {
+                    text: "bold red".into(),
+                    style: style(Some(AnsiColor::Indexed(1)), true),
+                },
+                AnsiSpan {
+                    text: " red".into(),
+                    style: style(Some(AnsiColor::Indexed(1)), false),
+                },
+                AnsiSpan {
+                    text: " ".into(),
+                    style: AnsiStyle::default(),
+                },
+                AnsiSpan {
+                    text: "underline".into(),
+                    style: AnsiStyle {
+                        underline: true,
+                        ..AnsiStyle::default()
+                    },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_extended_colors() {
+        let spans = parse("\x1b[38;5;196;48;2;1;2;3mx\x1b[92;105my");
+        assert_eq!(spans[0].style.foreground, Some(AnsiColor::Indexed(196)));
+        assert_eq!(spans[0].style.background, Some(AnsiColor::Rgb(1, 2, 3)));
+        assert_eq!(spans[1].style.foreground, Some(AnsiColor::Indexed(10)));
+        assert_eq!(spans[1].style.background, Some(AnsiColor::Indexed(13)));
+
+        assert_eq!(AnsiColor::Indexed(196).to_rgb(), (255, 0, 0));
+        assert_eq!(AnsiColor::Indexed(244).to_rgb(), (128, 128, 128));
+
+        // truncated extended colours are ignored
+        assert_eq!(parse("\x1b[38;2;1mx")[0].style, AnsiStyle::default());
+    }
+
+    #[test]
+    fn test_strip() {
+        assert_eq!(strip("\x1b[2J\x1b[Hclear\x1b[1;32m ok\x1b[0m"), "clear ok");
+        assert_eq!(strip("\x1b]0;title\x07a\x1b]8;;link\x1b\\b"), "ab");
+        assert_eq!(strip("unterminated \x1b[31"), "unterminated ");
+        assert_eq!(strip("trailing \x1b"), "trailing ");
+    }
+
+    proptest! {
+        #[test]
+        fn test_strip_removes_escapes(s in "(\x1b|\\[|[0-9;]|m|[a-z ]|é)*") {
+            let stripped = strip(&s);
+            prop_assert!(!has_escapes(&stripped));
+            prop_assert_eq!(strip(&stripped), stripped.clone());
+        }
+    }
+}
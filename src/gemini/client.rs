@@ -1,33 +1,75 @@
-use std::hint::unreachable_unchecked;
+use std::sync::Arc;
 
-use iced::widget::image::Handle;
-use rustls::crypto::CryptoProvider;
+use rustls::{crypto::CryptoProvider, pki_types::CertificateDer};
 use thiserror::Error;
 use url::Url;
 
 use crate::{
-    Message,
-    gemini::{
-        Status,
-        gemtext::{Document, Line},
-        response::Response,
-    },
-    net::{tofu_cert_verifier::TofuCertVerifier, tofu_socket::TofuSocket},
+    gemini::{Status, content::ContentHandlers, gemtext::Document, response::Response},
+    net::{identity::Identity, tofu_cert_verifier::TofuCertVerifier, tofu_socket::TofuSocket},
 };
 
 const MAX_REDIRECTS: usize = 5;
+const GEMINI_PORT: u16 = 1965;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct Client {
     verifier: TofuCertVerifier,
+    content_handlers: Arc<ContentHandlers>,
+    identity: Option<Arc<Identity>>,
+    max_redirects: usize,
+    max_response_size: Option<u64>,
 }
 
 #[derive(Error, Debug)]
 enum ClientError {
     #[error("ClientError: Missing context: {0}")]
     MissingContext(String),
+    #[error("ClientError: No response body")]
+    MissingBody,
+    #[error("ClientError: not following a redirect from {0} to {1}")]
+    CrossSchemeRedirect(Url, Url),
+}
+
+/// The final response to a request, after following any redirects.
+#[derive(Debug, Clone)]
+pub struct Exchange {
+    pub url: Url,
+    pub response: Response,
+    /// The certificate presented by the server that sent `response`.
+    pub certificate: Option<CertificateDer<'static>>,
 }
+
+/// A page produced by a content handler, along with the MIME type it was served as.
+#[derive(Debug, Clone)]
+pub struct PageContent {
+    pub mime: mime::Mime,
+    pub document: Document,
+}
+
+/// The result of fetching a URL, after following redirects. `Url` is always
+/// the final URL.
+#[derive(Debug, Clone)]
+pub enum FetchOutcome {
+    /// A success response that a content handler turned into a page.
+    Page(Url, PageContent),
+    /// A success response that no content handler understands.
+    Unsupported(Url, mime::Mime, Vec<u8>),
+    /// The server is asking for input, optionally with a prompt.
+    Input {
+        url: Url,
+        prompt: Option<String>,
+        sensitive: bool,
+    },
+    /// Any other status, with the server's error message if it sent one.
+    Failure(Url, Status, Option<String>),
+}
+
 impl Client {
+    /// Creates a client with the built-in content handlers.
+    ///
+    /// # Panics
+    /// If no default rustls `CryptoProvider` has been installed.
     pub fn new() -> Self {
         Self {
             verifier: TofuCertVerifier::new(
@@ -35,89 +77,83 @@ impl Client {
                     .expect("TofuCertVerifier: no default CryptoProvider")
                     .signature_verification_algorithms,
             ),
+            content_handlers: Arc::new(ContentHandlers::default()),
+            identity: None,
+            max_redirects: MAX_REDIRECTS,
+            max_response_size: None,
         }
     }
 
-    fn success(url: Url, response: Response) -> Message {
-        log::info!("load_page: Success! Rendering page");
-        let Some(body) = response.body else {
-            return Message::Error("No response body".into());
-        };
+    #[must_use]
+    pub fn with_content_handlers(mut self, content_handlers: ContentHandlers) -> Self {
+        self.content_handlers = Arc::new(content_handlers);
+        self
+    }
 
-        log::info!("success: got MIME type: {:?}", response.ctx);
-        let mime: mime::Mime = match response.ctx.unwrap_or("text/gemini".into()).parse() {
-            Ok(x) => x,
-            Err(e) => return Message::Error(e.to_string()),
-        };
+    /// Presents `identity` as the client certificate on requests to the host
+    /// (and port) of the URL requested, including redirects within it.
+    #[must_use]
+    pub fn with_identity(mut self, identity: Identity) -> Self {
+        self.identity = Some(Arc::new(identity));
+        self
+    }
 
-        match (mime.type_(), mime.subtype()) {
-            (mime::TEXT, x) if x.as_str() == "gemini" => {
-                let utf8_body = match String::from_utf8(body) {
-                    Ok(x) => x,
-                    Err(e) => {
-                        return Message::Error(format!("body contains bad utf8 data: {e:?}"));
-                    }
-                };
-
-                let document = match Document::parse(&url, &utf8_body) {
-                    Ok(x) => x,
-                    Err(e) => return Message::Error(e.to_string()),
-                };
-
-                Message::Loaded(url, Some(document))
-            }
-            (mime::TEXT, _) => {
-                let utf8_body = match String::from_utf8(body) {
-                    Ok(x) => x,
-                    Err(e) => {
-                        return Message::Error(format!("body contains bad utf8 data: {e:?}"));
-                    }
-                };
-
-                let document = Document::from_lines(&url, [Line::PreFormatted(utf8_body)]);
-                Message::Loaded(url, Some(document))
-            }
-            (mime::IMAGE, _) => {
-                let handle = Handle::from_bytes(body);
-                let doc = Document {
-                    url: url.clone(),
-                    lines: vec![Line::Image(handle)],
-                };
-                Message::Loaded(url, Some(doc))
-            }
-            _ => Message::Error("unsupported MIME type".into()),
-        }
+    /// How many redirects to follow before returning the redirect response
+    /// itself. Zero disables following redirects.
+    #[must_use]
+    pub fn with_max_redirects(mut self, max_redirects: usize) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    /// Fails any response longer than `max_response_size` bytes, header
+    /// included, instead of reading all of it.
+    #[must_use]
+    pub fn with_max_response_size(mut self, max_response_size: u64) -> Self {
+        self.max_response_size = Some(max_response_size);
+        self
     }
 
-    pub fn load_page(&self, url: &Url) -> Message {
-        let (url, response) = match self.request(url) {
-            Ok(x) => x,
-            Err(e) => return Message::Error("load_page: request: ".to_string() + &e.to_string()),
+    fn success(&self, url: Url, response: Response) -> anyhow::Result<FetchOutcome> {
+        log::info!("fetch: Success! Rendering page");
+        let body = response.body.ok_or(ClientError::MissingBody)?;
+
+        log::info!("success: got MIME type: {:?}", response.ctx);
+        let mime: mime::Mime = response.ctx.unwrap_or("text/gemini".into()).parse()?;
+
+        let Some(handler) = self.content_handlers.find(&mime) else {
+            return Ok(FetchOutcome::Unsupported(url, mime, body));
         };
+        let document = handler.handle(&url, &mime, body)?;
+        Ok(FetchOutcome::Page(url, PageContent { mime, document }))
+    }
+
+    /// Fetches `url`, optionally through `proxy`, and converts the response
+    /// into a `FetchOutcome`.
+    pub fn fetch(&self, url: &Url, proxy: Option<&Url>) -> anyhow::Result<FetchOutcome> {
+        let Exchange { url, response, .. } = self.request(url, proxy)?;
 
         match response.status {
-            Status::Success => Client::success(url, response),
-            // TODO -> blur the input on the client for sensitive input
-            Status::InputExpected | Status::SensitiveInput => Message::InputExpected(url, response),
-            Status::ClientCertificate => todo!(),
-            Status::CertificateNotAuthorized => todo!(),
-            Status::CertificateNotValid => todo!(),
-            _ => Message::Error(format!(
-                "Error status: {:?}: {:?}",
-                response.status, response.ctx
-            )),
+            Status::Success => self.success(url, response),
+            Status::InputExpected | Status::SensitiveInput => Ok(FetchOutcome::Input {
+                sensitive: response.status == Status::SensitiveInput,
+                prompt: response.ctx,
+                url,
+            }),
+            status => Ok(FetchOutcome::Failure(url, status, response.ctx)),
         }
     }
 
-    pub fn request(&self, url: &Url) -> anyhow::Result<(Url, Response)> {
-        let mut sock = TofuSocket::new(url.clone(), self.verifier)?;
-
-        let res = sock.request(format!("{url}\r\n").as_bytes())?;
-        let mut r: Response = (&res[..]).try_into()?;
+    /// Requests `url`, following redirects. If `proxy` is given, the request
+    /// (and any redirects) are sent to the proxy's host instead.
+    pub fn request(&self, url: &Url, proxy: Option<&Url>) -> anyhow::Result<Exchange> {
+        let identity = self.identity.as_deref();
+        let (mut r, mut certificate) = self.request_once(url, proxy, identity)?;
+        let origin = url;
         let mut url = url.clone();
 
         let mut num_redirects = 0;
-        while (30..=39).any(|x| x == r.status as u8) && num_redirects <= MAX_REDIRECTS {
+        while (30..=39).any(|x| x == r.status as u8) && num_redirects < self.max_redirects {
             num_redirects += 1;
             // redirect
             log::info!("Client: request: redirecting to {:?}", r.ctx);
@@ -126,20 +162,104 @@ impl Client {
                 r.status
             )))?;
 
-            // a socket is only good for one gemini request-response cycle, we need to make a new one
-            url = url.join(&ctx)?;
-            let mut sock = TofuSocket::new(url.clone(), self.verifier)?;
-            let res = sock.request(format!("{url}\r\n").as_bytes())?;
-            log::info!("Client: request: res: {:?}", str::from_utf8(&res));
-            r = (&res[..]).try_into()?;
+            url = match proxy {
+                // the proxy decides which schemes it can fetch
+                Some(_) => url.join(&ctx)?,
+                None => redirect_target(&url, &ctx)?,
+            };
+            // a redirect elsewhere mustn't be able to learn who the user is
+            let identity = identity.filter(|_| same_host(origin, &url));
+            (r, certificate) = self.request_once(&url, proxy, identity)?;
         }
 
-        Ok((url, r))
+        Ok(Exchange {
+            url,
+            response: r,
+            certificate,
+        })
+    }
+
+    fn request_once(
+        &self,
+        url: &Url,
+        proxy: Option<&Url>,
+        identity: Option<&Identity>,
+    ) -> anyhow::Result<(Response, Option<CertificateDer<'static>>)> {
+        // a socket is only good for one gemini request-response cycle, we need a new one each time
+        let mut sock = TofuSocket::new(proxy.unwrap_or(url).clone(), self.verifier, identity)?;
+        let res = sock.request(format!("{url}\r\n").as_bytes(), self.max_response_size)?;
+        log::debug!("Client: request: res: {:?}", str::from_utf8(&res));
+        Ok(((&res[..]).try_into()?, sock.peer_certificate()))
     }
 }
 
+/// Resolves a redirect from `url` to `ctx`. Redirects to another scheme,
+/// e.g. from `gemini://` to `http://`, are refused: following them would
+/// send the request to a server speaking a different protocol.
+fn redirect_target(url: &Url, ctx: &str) -> anyhow::Result<Url> {
+    let target = url.join(ctx)?;
+    if target.scheme() != url.scheme() {
+        return Err(ClientError::CrossSchemeRedirect(url.clone(), target).into());
+    }
+    Ok(target)
+}
+
+/// Whether `a` and `b` are on the same host and port, where a client
+/// certificate for one is also meant for the other.
+fn same_host(a: &Url, b: &Url) -> bool {
+    // gemini hosts are opaque to `url`, so aren't lowercased
+    let host = |x: &Url| x.host_str().map(str::to_ascii_lowercase);
+    let port = |x: &Url| x.port_or_known_default().unwrap_or(GEMINI_PORT);
+    host(a) == host(b) && port(a) == port(b)
+}
+
 impl Default for Client {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_redirect_target() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/a/b")?;
+        assert_eq!(
+            redirect_target(&url, "c")?.as_str(),
+            "gemini://example.com/a/c"
+        );
+        assert_eq!(
+            redirect_target(&url, "//other.example/")?.as_str(),
+            "gemini://other.example/"
+        );
+        assert!(redirect_target(&url, "https://example.com/").is_err());
+        assert!(redirect_target(&url, "GEMINI://example.com/").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_same_host() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/private")?;
+        let same = |x| Ok::<_, url::ParseError>(same_host(&url, &Url::parse(x)?));
+        assert!(same("gemini://example.com/elsewhere")?);
+        assert!(same("gemini://EXAMPLE.com:1965/")?);
+        assert!(!same("gemini://other.example/private")?);
+        assert!(!same("gemini://example.com:1966/private")?);
+        assert!(!same("gemini://sub.example.com/private")?);
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn test_redirect_target_keeps_scheme(ctx in any::<String>()) {
+            let url = Url::parse("gemini://example.com/a/b").unwrap();
+            if let Ok(target) = redirect_target(&url, &ctx) {
+                prop_assert_eq!(target.scheme(), "gemini");
+            }
+        }
+    }
+}
@@ -0,0 +1,406 @@
+use std::{fmt, str::FromStr};
+
+use thiserror::Error;
+use url::Url;
+
+use crate::gemini::{
+    feed::Feed,
+    gemtext::{Document, Line},
+    html, markdown,
+};
+
+#[derive(Error, Debug)]
+pub enum ContentError {
+    #[error("invalid MIME pattern: `{0}`")]
+    InvalidPattern(String),
+    #[error("body contains bad utf8 data: {0}")]
+    BadUtf8(#[from] std::string::FromUtf8Error),
+}
+
+/// Turns the body of a successful response into a renderable `Document`.
+pub trait ContentHandler: Send + Sync {
+    fn handle(&self, url: &Url, mime: &mime::Mime, body: Vec<u8>) -> anyhow::Result<Document>;
+}
+
+/// A MIME type to match responses against, e.g. `text/gemini`, `image/*` or `*/*`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MimePattern {
+    type_: String,
+    subtype: String, // `*` matches any subtype
+}
+
+impl MimePattern {
+    pub fn matches(&self, mime: &mime::Mime) -> bool {
+        // `mime` splits e.g. `atom+xml` into a subtype and a suffix
+        let subtype = match mime.suffix() {
+            Some(suffix) => format!("{}+{}", mime.subtype(), suffix),
+            None => mime.subtype().to_string(),
+        };
+        (self.type_ == "*" || self.type_ == mime.type_().as_str())
+            && (self.subtype == "*" || self.subtype == subtype)
+    }
+
+    /// How specific the pattern is, used to prefer `text/gemini` over `text/*`.
+    fn specificity(&self) -> u8 {
+        u8::from(self.type_ != "*") + u8::from(self.subtype != "*")
+    }
+}
+
+impl FromStr for MimePattern {
+    type Err = ContentError;
+
+    /// Parses `type/subtype`. A bare `type` is treated as `type/*`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (type_, subtype) = s.split_once('/').unwrap_or((s, "*"));
+        let (type_, subtype) = (type_.trim(), subtype.trim());
+        if type_.is_empty() || subtype.is_empty() || (type_ == "*" && subtype != "*") {
+            return Err(ContentError::InvalidPattern(s.to_string()));
+        }
+
+        Ok(Self {
+            type_: type_.to_ascii_lowercase(),
+            subtype: subtype.to_ascii_lowercase(),
+        })
+    }
+}
+
+impl fmt::Display for MimePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.type_, self.subtype)
+    }
+}
+
+/// The set of content handlers, keyed by MIME pattern. The most specific
+/// matching pattern wins; between equally specific patterns, the one
+/// registered last wins, so built-in handlers can be overridden.
+pub struct ContentHandlers {
+    handlers: Vec<(MimePattern, Box<dyn ContentHandler>)>,
+}
+
+impl ContentHandlers {
+    /// An empty registry, without any of the built-in handlers.
+    pub fn empty() -> Self {
+        Self {
+            handlers: Vec::new(),
+        }
+    }
+
+    pub fn register(&mut self, pattern: MimePattern, handler: impl ContentHandler + 'static) {
+        self.handlers.push((pattern, Box::new(handler)));
+    }
+
+    pub fn find(&self, mime: &mime::Mime) -> Option<&dyn ContentHandler> {
+        let mut best: Option<&(MimePattern, Box<dyn ContentHandler>)> = None;
+        for entry in self.handlers.iter().rev() {
+            if entry.0.matches(mime)
+                && best.is_none_or(|x| entry.0.specificity() > x.0.specificity())
+            {
+                best = Some(entry);
+            }
+        }
+        best.map(|(_, handler)| handler.as_ref())
+    }
+}
+
+impl Default for ContentHandlers {
+    fn default() -> Self {
+        let mut handlers = Self::empty();
+        handlers.register(pattern("text/*"), PlainTextHandler);
+        handlers.register(pattern("text/gemini"), GemtextHandler);
+        handlers.register(pattern("image/*"), ImageHandler);
+        handlers.register(pattern("application/atom+xml"), FeedHandler);
+        handlers.register(pattern("application/rss+xml"), FeedHandler);
+        handlers.register(pattern("text/markdown"), MarkdownHandler);
+        handlers.register(pattern("text/html"), HtmlHandler);
+        handlers.register(pattern("application/xhtml+xml"), HtmlHandler);
+        handlers
+    }
+}
+
+impl fmt::Debug for ContentHandlers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.handlers.iter().map(|(pattern, _)| pattern.to_string()))
+            .finish()
+    }
+}
+
+/// Guesses a URL's MIME type from its file extension, for deciding what to
+/// do with a link before fetching it.
+pub fn guess_mime(url: &Url) -> Option<mime::Mime> {
+    let name = url.path_segments()?.next_back()?;
+    let (_, extension) = name.rsplit_once('.')?;
+    let mime = match extension.to_ascii_lowercase().as_str() {
+        "gmi" | "gemini" => "text/gemini",
+        "txt" => "text/plain",
+        "md" | "markdown" => "text/markdown",
+        "html" | "htm" => "text/html",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "svg" => "image/svg+xml",
+        "atom" => "application/atom+xml",
+        "rss" => "application/rss+xml",
+        _ => return None,
+    };
+    mime.parse().ok()
+}
+
+fn pattern(s: &str) -> MimePattern {
+    s.parse().expect("built-in MIME patterns are valid")
+}
+
+/// `text/gemini`, parsed into gemtext lines.
+#[derive(Debug, Clone, Copy)]
+pub struct GemtextHandler;
+
+impl ContentHandler for GemtextHandler {
+    fn handle(&self, url: &Url, _mime: &mime::Mime, body: Vec<u8>) -> anyhow::Result<Document> {
+        let utf8_body = String::from_utf8(body).map_err(ContentError::from)?;
+        Ok(Document::parse(url, &utf8_body))
+    }
+}
+
+/// Any other `text/*`, shown as a single preformatted block. Anything but
+/// `text/plain` keeps its MIME type as the document's language, so the UI can
+/// tell e.g. `text/x-rust` apart.
+#[derive(Debug, Clone, Copy)]
+pub struct PlainTextHandler;
+
+impl ContentHandler for PlainTextHandler {
+    fn handle(&self, url: &Url, mime: &mime::Mime, body: Vec<u8>) -> anyhow::Result<Document> {
+        let utf8_body = String::from_utf8(body).map_err(ContentError::from)?;
+        let mut doc = Document::from_lines(url, [Line::PreFormatted(utf8_body, None)]);
+        doc.language =
+            Some(mime.essence_str().to_string()).filter(|_| mime.subtype() != mime::PLAIN);
+        Ok(doc)
+    }
+}
+
+/// `image/*`, shown on its own.
+#[derive(Debug, Clone, Copy)]
+pub struct ImageHandler;
+
+impl ContentHandler for ImageHandler {
+    fn handle(&self, url: &Url, _mime: &mime::Mime, body: Vec<u8>) -> anyhow::Result<Document> {
+        Ok(Document::from_lines(url, [Line::Image(body.into())]))
+    }
+}
+
+/// `application/atom+xml` and `application/rss+xml`, listed as a page of
+/// links to their entries.
+#[derive(Debug, Clone, Copy)]
+pub struct FeedHandler;
+
+impl ContentHandler for FeedHandler {
+    fn handle(&self, url: &Url, _mime: &mime::Mime, body: Vec<u8>) -> anyhow::Result<Document> {
+        let utf8_body = String::from_utf8(body).map_err(ContentError::from)?;
+        Ok(Feed::parse(url, &utf8_body)?.to_document(url))
+    }
+}
+
+/// `text/markdown`, converted into gemtext lines.
+#[derive(Debug, Clone, Copy)]
+pub struct MarkdownHandler;
+
+impl ContentHandler for MarkdownHandler {
+    fn handle(&self, url: &Url, _mime: &mime::Mime, body: Vec<u8>) -> anyhow::Result<Document> {
+        let utf8_body = String::from_utf8(body).map_err(ContentError::from)?;
+        Ok(markdown::to_document(url, &utf8_body))
+    }
+}
+
+/// `text/html` and `application/xhtml+xml`, reduced to gemtext lines.
+#[derive(Debug, Clone, Copy)]
+pub struct HtmlHandler;
+
+impl ContentHandler for HtmlHandler {
+    fn handle(&self, url: &Url, _mime: &mime::Mime, body: Vec<u8>) -> anyhow::Result<Document> {
+        let utf8_body = String::from_utf8(body).map_err(ContentError::from)?;
+        Ok(html::to_document(url, &utf8_body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mime_pattern() -> anyhow::Result<()> {
+        let gemini: mime::Mime = "text/gemini; charset=utf-8".parse()?;
+        let plain: mime::Mime = "text/plain".parse()?;
+
+        assert!(pattern("text/gemini").matches(&gemini));
+        assert!(!pattern("text/gemini").matches(&plain));
+        assert!(pattern("text/*").matches(&plain));
+        assert!(pattern("TEXT").matches(&plain));
+        assert!(pattern("*/*").matches(&plain));
+        assert!("*/plain".parse::<MimePattern>().is_err());
+        assert!("/plain".parse::<MimePattern>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_most_specific() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/")?;
+        let handlers = ContentHandlers::default();
+
+        let doc = handlers.find(&"text/gemini".parse()?).unwrap().handle(
+            &url,
+            &"text/gemini".parse()?,
+            b"# hello\n".to_vec(),
+        )?;
+        assert!(matches!(&doc.lines[..], [Line::Heading(1, _)]));
+
+        let doc = handlers.find(&"text/plain".parse()?).unwrap().handle(
+            &url,
+            &"text/plain".parse()?,
+            b"# hello\n".to_vec(),
+        )?;
+        assert!(matches!(&doc.lines[..], [Line::PreFormatted(s, None)] if s == "# hello\n"));
+        assert_eq!(doc.language, None);
+
+        let doc = handlers.find(&"text/x-rust".parse()?).unwrap().handle(
+            &url,
+            &"text/x-rust; charset=utf-8".parse()?,
+            b"fn main() {}\n".to_vec(),
+        )?;
+        assert!(matches!(&doc.lines[..], [Line::PreFormatted(_, None)]));
+        assert_eq!(doc.language.as_deref(), Some("text/x-rust"));
+
+        assert!(handlers.find(&"audio/ogg".parse()?).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_later_registration_overrides() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/")?;
+        let mut handlers = ContentHandlers::default();
+        handlers.register(pattern("text/*"), GemtextHandler);
+
+        let doc = handlers.find(&"text/plain".parse()?).unwrap().handle(
+            &url,
+            &"text/plain".parse()?,
+            b"# hello\n".to_vec(),
+        )?;
+        assert!(matches!(&doc.lines[..], [Line::Heading(1, _)]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_utf8() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/")?;
+        let mime = "text/gemini".parse()?;
+        assert!(
+            GemtextHandler
+                .handle(&url, &mime, vec![0xff, 0xfe])
+                .is_err()
+        );
+        assert!(
+            PlainTextHandler
+                .handle(&url, &mime, vec![0xff, 0xfe])
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_guess_mime() -> anyhow::Result<()> {
+        let guess = |s| Url::parse(s).map(|url| guess_mime(&url));
+        assert_eq!(
+            guess("gemini://example.com/cat.PNG")?,
+            Some(mime::IMAGE_PNG)
+        );
+        assert_eq!(
+            guess("gemini://example.com/a.b/c.jpg?x=1")?,
+            Some(mime::IMAGE_JPEG)
+        );
+        assert_eq!(
+            guess("gemini://example.com/index.gmi")?.unwrap(),
+            "text/gemini"
+        );
+        assert_eq!(guess("gemini://example.com/")?, None);
+        assert_eq!(guess("gemini://example.com/a.b/c")?, None);
+        assert_eq!(guess("mailto:a@example.com")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_image() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/cat.png")?;
+        let doc = ImageHandler.handle(&url, &"image/png".parse()?, vec![1, 2, 3])?;
+        assert!(matches!(&doc.lines[..], [Line::Image(data)] if data[..] == [1, 2, 3]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_feed() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/atom.xml")?;
+        let handlers = ContentHandlers::default();
+        let mime: mime::Mime = "application/atom+xml".parse()?;
+        let body = r#"<feed xmlns="http://www.w3.org/2005/Atom"><title>Log</title></feed>"#;
+        let doc = handlers
+            .find(&mime)
+            .unwrap()
+            .handle(&url, &mime, body.into())?;
+        assert_eq!(doc.lines, [Line::Heading(1, "Log".into())]);
+        assert!(
+            handlers
+                .find(&mime)
+                .unwrap()
+                .handle(&url, &mime, b"<html/>".to_vec())
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_markdown() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/README.md")?;
+        let handlers = ContentHandlers::default();
+        let mime: mime::Mime = "text/markdown; charset=utf-8".parse()?;
+        let doc = handlers.find(&mime).unwrap().handle(
+            &url,
+            &mime,
+            b"# hello
+
+* world
+"
+            .to_vec(),
+        )?;
+        assert_eq!(
+            doc.lines,
+            [
+                Line::Heading(1, "hello".into()),
+                Line::Text(String::new()),
+                Line::List("world".into()),
+            ]
+        );
+        assert_eq!(guess_mime(&url).unwrap(), "text/markdown");
+        Ok(())
+    }
+
+    #[test]
+    fn test_html() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/index.html")?;
+        let handlers = ContentHandlers::default();
+        let mime: mime::Mime = "text/html; charset=utf-8".parse()?;
+        let doc = handlers.find(&mime).unwrap().handle(
+            &url,
+            &mime,
+            b"<h1>hello</h1><script>alert(1)</script><ul><li>world</ul>".to_vec(),
+        )?;
+        assert_eq!(
+            doc.lines,
+            [
+                Line::Heading(1, "hello".into()),
+                Line::Text(String::new()),
+                Line::List("world".into()),
+            ]
+        );
+        assert_eq!(guess_mime(&url).unwrap(), "text/html");
+        Ok(())
+    }
+}
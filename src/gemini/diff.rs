@@ -0,0 +1,164 @@
+use crate::gemini::gemtext::Line;
+
+/// Past this many cells in the comparison table, the changed middle of the
+/// documents is shown as removed and re-added instead of compared.
+const MAX_TABLE_CELLS: usize = 1 << 22;
+
+/// What happened to a line between two versions of a document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Unchanged,
+    Added,
+    Removed,
+}
+
+/// Compares two versions of a document line by line. Returns the lines of
+/// `new`, with the lines only in `old` put back where they were removed, so
+/// reading the `Unchanged` and `Removed` lines gives `old` and reading the
+/// `Unchanged` and `Added` lines gives `new`.
+pub fn diff(old: &[Line], new: &[Line]) -> Vec<(Change, Line)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old_middle, new_middle) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    let mut out: Vec<(Change, Line)> = old[..prefix]
+        .iter()
+        .map(|x| (Change::Unchanged, x.clone()))
+        .collect();
+    out.extend(diff_middle(old_middle, new_middle));
+    out.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|x| (Change::Unchanged, x.clone())),
+    );
+    out
+}
+
+/// Diffs the parts of the documents between their common prefix and suffix,
+/// using the longest common subsequence of their lines.
+fn diff_middle(old: &[Line], new: &[Line]) -> Vec<(Change, Line)> {
+    let (n, m) = (old.len(), new.len());
+    if (n + 1).saturating_mul(m + 1) > MAX_TABLE_CELLS {
+        log::info!("diff: {n} by {m} lines is too many to compare");
+        return old
+            .iter()
+            .map(|x| (Change::Removed, x.clone()))
+            .chain(new.iter().map(|x| (Change::Added, x.clone())))
+            .collect();
+    }
+
+    // lcs[i * (m + 1) + j] is the length of the LCS of old[i..] and new[j..]
+    let mut lcs = vec![0_u32; (n + 1) * (m + 1)];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i * (m + 1) + j] = if old[i] == new[j] {
+                lcs[(i + 1) * (m + 1) + j + 1] + 1
+            } else {
+                lcs[(i + 1) * (m + 1) + j].max(lcs[i * (m + 1) + j + 1])
+            };
+        }
+    }
+
+    let mut out = Vec::with_capacity(n.max(m));
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old[i] == new[j] {
+            out.push((Change::Unchanged, new[j].clone()));
+            i += 1;
+            j += 1;
+        } else if j == m || (i < n && lcs[(i + 1) * (m + 1) + j] >= lcs[i * (m + 1) + j + 1]) {
+            out.push((Change::Removed, old[i].clone()));
+            i += 1;
+        } else {
+            out.push((Change::Added, new[j].clone()));
+            j += 1;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use url::Url;
+
+    use super::*;
+    use crate::gemini::gemtext::Document;
+
+    fn lines(s: &str) -> Vec<Line> {
+        Document::parse(&Url::parse("gemini://example.com/").unwrap(), s).lines
+    }
+
+    fn render(changes: &[(Change, Line)]) -> Vec<String> {
+        changes
+            .iter()
+            .map(|(change, line)| {
+                let marker = match change {
+                    Change::Unchanged => ' ',
+                    Change::Added => '+',
+                    Change::Removed => '-',
+                };
+                let Line::Text(s) = line else {
+                    panic!("unexpected line {line:?}");
+                };
+                format!("{marker}{s}")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = lines("a\nb\nc\nd\ne\n");
+        let new = lines("a\nc\nd\nx\ne\nf\n");
+        assert_eq!(
+            render(&diff(&old, &new)),
+            [" a", "-b", " c", " d", "+x", " e", "+f"]
+        );
+
+        assert_eq!(render(&diff(&old, &old)), [" a", " b", " c", " d", " e"]);
+        assert_eq!(render(&diff(&[], &lines("a\n"))), ["+a"]);
+        assert_eq!(render(&diff(&lines("a\n"), &[])), ["-a"]);
+    }
+
+    #[test]
+    fn test_diff_changed_line() {
+        let old = lines("# Status\nAll good\n");
+        let new = lines("# Status\nDown for maintenance\n");
+        let changes = diff(&old, &new);
+        assert_eq!(changes.len(), 3);
+        assert_eq!(
+            changes[0],
+            (Change::Unchanged, Line::Heading(1, "Status".into()))
+        );
+        assert_eq!(changes[1], (Change::Removed, Line::Text("All good".into())));
+        assert_eq!(
+            changes[2],
+            (Change::Added, Line::Text("Down for maintenance".into()))
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_diff_gives_back_both(old in "[abc\n]{0,40}", new in "[abc\n]{0,40}") {
+            let (old, new) = (lines(&old), lines(&new));
+            let changes = diff(&old, &new);
+            let side = |skip| {
+                changes
+                    .iter()
+                    .filter(|(change, _)| *change != skip)
+                    .map(|(_, line)| line.clone())
+                    .collect::<Vec<_>>()
+            };
+            prop_assert_eq!(side(Change::Added), old);
+            prop_assert_eq!(side(Change::Removed), new);
+        }
+    }
+}
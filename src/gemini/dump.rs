@@ -0,0 +1,196 @@
+use std::fmt::Write;
+
+use crate::gemini::gemtext::{Document, Line};
+
+const DEFAULT_WIDTH: usize = 80;
+
+/// Renders a `Document` as wrapped plain text for terminals and the
+/// clipboard, in the style of `lynx -dump`: links are numbered inline and
+/// listed under a `References` section at the end.
+#[derive(Debug, Clone)]
+pub struct TextRenderer {
+    width: usize,
+}
+
+impl TextRenderer {
+    pub fn new() -> Self {
+        Self {
+            width: DEFAULT_WIDTH,
+        }
+    }
+
+    /// The column to wrap text at. Preformatted blocks are never wrapped.
+    #[must_use]
+    pub fn width(mut self, width: usize) -> Self {
+        self.width = width;
+        self
+    }
+
+    pub fn render(&self, document: &Document) -> String {
+        let mut out = String::new();
+        let mut links = Vec::new();
+
+        for line in &document.lines {
+            match line {
+                Line::Text(s) => self.wrap(&mut out, s, "", ""),
+                Line::Link(url, friendly) => {
+                    links.push(url);
+                    let label = friendly.as_deref().unwrap_or(url.as_str());
+                    let marker = format!("[{}] ", links.len());
+                    let indent = " ".repeat(marker.len());
+                    self.wrap(&mut out, label, &marker, &indent);
+                }
+                Line::Heading(1, s) => self.underline(&mut out, s, '='),
+                Line::Heading(2, s) => self.underline(&mut out, s, '-'),
+                Line::Heading(level, s) => {
+                    let marker = format!("{} ", "#".repeat(*level));
+                    let indent = " ".repeat(marker.len());
+                    self.wrap(&mut out, s, &marker, &indent);
+                }
+                Line::List(s) => self.wrap(&mut out, s, "  * ", "    "),
+                Line::Quote(s) => self.wrap(&mut out, s, "  > ", "  > "),
+                Line::PreFormatted(s, _) => {
+                    for line in s.lines() {
+                        push_line(&mut out, &format!("    {line}"));
+                    }
+                }
+                Line::Image(_) => push_line(&mut out, "[image]"),
+                Line::Toggle(_) => {}
+            }
+        }
+
+        if !links.is_empty() {
+            out.push_str("\nReferences\n\n");
+            for (i, url) in links.iter().enumerate() {
+                let _ = writeln!(out, "{:>4}. {url}", i + 1);
+            }
+        }
+        out
+    }
+
+    /// Word-wraps `s`, starting the first line with `first` and any others
+    /// with `rest`. Words longer than the width are left unbroken.
+    fn wrap(&self, out: &mut String, s: &str, first: &str, rest: &str) {
+        let mut line = first.to_string();
+        let mut line_len = first.chars().count();
+        let mut empty = true;
+
+        for word in s.split_whitespace() {
+            let word_len = word.chars().count();
+            if !empty && line_len + 1 + word_len > self.width {
+                push_line(out, &line);
+                line = rest.to_string();
+                line_len = rest.chars().count();
+                empty = true;
+            }
+            if !empty {
+                line.push(' ');
+                line_len += 1;
+            }
+            line.push_str(word);
+            line_len += word_len;
+            empty = false;
+        }
+        push_line(out, &line);
+    }
+
+    fn underline(&self, out: &mut String, s: &str, c: char) {
+        let start = out.len();
+        self.wrap(out, s, "", "");
+        let longest = out[start..]
+            .lines()
+            .map(|x| x.chars().count())
+            .max()
+            .unwrap_or(0);
+        push_line(out, &c.to_string().repeat(longest));
+    }
+}
+
+impl Default for TextRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn push_line(out: &mut String, line: &str) {
+    out.push_str(line.trim_end());
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::*;
+
+    #[test]
+    fn test_wrap() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/")?;
+        let doc = Document::from_lines(
+            &url,
+            [
+                Line::Text("one two three four five".into()),
+                Line::List("alpha beta gamma".into()),
+                Line::Quote("quoted text here".into()),
+                Line::Text(String::new()),
+                Line::Text("unbreakable-word-longer-than-width".into()),
+            ],
+        );
+        assert_eq!(
+            TextRenderer::new().width(14).render(&doc),
+            "one two three\n\
+             four five\n  \
+             * alpha beta\n    \
+             gamma\n  \
+             > quoted\n  \
+             > text here\n\
+             \n\
+             unbreakable-word-longer-than-width\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_headings_and_preformatted() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/")?;
+        let doc = Document::from_lines(
+            &url,
+            [
+                Line::Heading(1, "Title".into()),
+                Line::Heading(2, "Section".into()),
+                Line::Heading(3, "Sub".into()),
+                Line::PreFormatted(
+                    "a  long   preformatted line that is not wrapped\n".into(),
+                    None,
+                ),
+            ],
+        );
+        assert_eq!(
+            TextRenderer::new().width(20).render(&doc),
+            "Title\n=====\nSection\n-------\n### Sub\n    \
+             a  long   preformatted line that is not wrapped\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_link_references() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/")?;
+        let doc = Document::parse(
+            &url,
+            "=> /one First link\n=> gemini://other.example/\nsome text\n",
+        );
+        assert_eq!(
+            TextRenderer::new().render(&doc),
+            "[1] First link\n\
+             [2] gemini://other.example/\n\
+             some text\n\
+             \n\
+             References\n\
+             \n   \
+             1. gemini://example.com/one\n   \
+             2. gemini://other.example/\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,696 @@
+//! Exports documents for sharing and reading offline: as a standalone HTML
+//! page, as Markdown, or bundled into an EPUB book.
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io::{Cursor, Seek, Write},
+};
+
+use thiserror::Error;
+use url::Url;
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+use crate::gemini::{
+    ansi,
+    gemtext::{Document, Line},
+};
+
+const STYLE: &str = "\
+body { font-family: sans-serif; line-height: 1.5; max-width: 40em; margin: 0 auto; padding: 1em; }
+h1, h2, h3 { line-height: 1.2; }
+p { margin: 0.25em 0; }
+p.blank { height: 0.75em; }
+p.link::before { content: \"⇒ \"; }
+blockquote { border-left: 3px solid #999; margin: 0.5em 0; padding-left: 1em; }
+pre { overflow-x: auto; padding: 0.5em; background: #f4f4f4; }
+@media (prefers-color-scheme: dark) {
+  body { background: #1e1e1e; color: #ddd; }
+  a { color: #8ab4f8; }
+  pre { background: #2a2a2a; }
+}
+";
+
+/// The document's first heading, or its URL if it has none.
+pub fn title(doc: &Document) -> String {
+    doc.lines
+        .iter()
+        .find_map(|line| match line {
+            Line::Heading(_, s) => Some(s.trim().to_string()),
+            _ => None,
+        })
+        .unwrap_or_else(|| doc.url.to_string())
+}
+
+/// A standalone HTML page, with its stylesheet embedded.
+pub fn to_html(doc: &Document) -> String {
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    let _ = writeln!(out, "<title>{}</title>", escape(&title(doc)));
+    let _ = writeln!(out, "<style>\n{STYLE}</style>\n</head>\n<body>");
+    write_body(&mut out, doc, &|url| url.to_string());
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Markdown, with anything that Markdown would read as markup escaped.
+/// Each line becomes a paragraph of its own, except for list items, which
+/// are kept together.
+pub fn to_markdown(doc: &Document) -> String {
+    let mut out = String::new();
+    let mut previous: Option<&Line> = None;
+    for line in &doc.lines {
+        let block = match line {
+            Line::Text(s) if s.trim().is_empty() => continue,
+            Line::Text(s) => escape_markdown(s.trim()),
+            Line::Link(url, label) if !is_linkable(url) => {
+                escape_markdown(&unlinked_label(url, label.as_deref()))
+            }
+            Line::Link(url, label) => match label {
+                Some(label) => format!("[{}]({})", escape_markdown(label), markdown_url(url)),
+                None => format!("<{url}>"),
+            },
+            Line::Heading(level, s) => {
+                format!("{} {}", "#".repeat(*level), escape_markdown(s.trim()))
+            }
+            Line::List(s) => format!("* {}", escape_markdown(s.trim())),
+            Line::Quote(s) => format!("> {}", escape_markdown(s.trim())),
+            Line::PreFormatted(s, alt) => {
+                let s = ansi::strip(s);
+                let alt = alt.as_deref().unwrap_or_default();
+                // the info string after a backtick fence can't hold backticks
+                let c = if alt.contains('`') { '~' } else { '`' };
+                // a fence longer than any run of its character inside it
+                let longest = s.split(|x| x != c).map(str::len).max().unwrap_or(0);
+                let fence = c.to_string().repeat(longest.max(2) + 1);
+                let newline = if s.ends_with('\n') || s.is_empty() {
+                    ""
+                } else {
+                    "\n"
+                };
+                format!("{fence}{alt}\n{s}{newline}{fence}")
+            }
+            Line::Image(_) => "\\[image\\]".to_string(),
+            Line::Toggle(_) => continue,
+        };
+        if previous.is_some() {
+            let list = matches!(previous, Some(Line::List(_))) && matches!(line, Line::List(_));
+            out.push_str(if list { "\n" } else { "\n\n" });
+        }
+        out.push_str(&block);
+        previous = Some(line);
+    }
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+/// The links in `doc` to other pages on the same capsule, without their
+/// fragments, in the order they're first linked to.
+pub fn capsule_links(doc: &Document) -> Vec<Url> {
+    let mut links: Vec<Url> = Vec::new();
+    for line in &doc.lines {
+        let Line::Link(url, _) = line else {
+            continue;
+        };
+        let mut url = url.clone();
+        url.set_fragment(None);
+        if url.scheme() == doc.url.scheme()
+            && url.host() == doc.url.host()
+            && url.port() == doc.url.port()
+            && url != doc.url
+            && !links.contains(&url)
+        {
+            links.push(url);
+        }
+    }
+    links
+}
+
+#[derive(Error, Debug)]
+pub enum EpubError {
+    #[error("a book needs at least one chapter")]
+    NoChapters,
+}
+
+/// An EPUB 3 book with one chapter per document, and a table of contents
+/// built from the chapters' headings. Links between chapters lead to each
+/// other inside the book.
+#[derive(Debug, Clone)]
+pub struct Epub {
+    title: String,
+    date: String,
+    chapters: Vec<Document>,
+}
+
+impl Epub {
+    /// `date` is when the book was made, as `YYYY-MM-DD`.
+    pub fn new(title: &str, date: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            date: date.to_string(),
+            chapters: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn chapter(mut self, doc: Document) -> Self {
+        self.chapters.push(doc);
+        self
+    }
+
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(self.write(Cursor::new(Vec::new()))?.into_inner())
+    }
+
+    /// Fails without any chapters, as the table of contents can't be empty.
+    pub fn write<W: Write + Seek>(&self, writer: W) -> anyhow::Result<W> {
+        if self.chapters.is_empty() {
+            return Err(EpubError::NoChapters.into());
+        }
+        let mut zip = ZipWriter::new(writer);
+        // the mimetype has to come first, uncompressed, for the format to be
+        // recognised without unzipping
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        zip.start_file("mimetype", stored)?;
+        zip.write_all(b"application/epub+zip")?;
+
+        let files = [
+            ("META-INF/container.xml".to_string(), CONTAINER.to_string()),
+            ("OEBPS/content.opf".to_string(), self.package()),
+            ("OEBPS/nav.xhtml".to_string(), self.nav()),
+            ("OEBPS/style.css".to_string(), STYLE.to_string()),
+        ];
+        let chapters = self.chapters.iter().enumerate().map(|(i, doc)| {
+            (
+                format!("OEBPS/{}", chapter_file(i)),
+                self.chapter_xhtml(doc),
+            )
+        });
+        for (name, contents) in files.into_iter().chain(chapters) {
+            zip.start_file(name, SimpleFileOptions::default())?;
+            zip.write_all(contents.as_bytes())?;
+        }
+        Ok(zip.finish()?)
+    }
+
+    fn package(&self) -> String {
+        let identifier = self
+            .chapters
+            .first()
+            .map_or_else(|| self.title.clone(), |x| x.url.to_string());
+        let mut manifest = String::new();
+        let mut spine = String::new();
+        for i in 0..self.chapters.len() {
+            let _ = writeln!(
+                manifest,
+                "<item id=\"chapter{i}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>",
+                chapter_file(i)
+            );
+            let _ = writeln!(spine, "<itemref idref=\"chapter{i}\"/>");
+        }
+        format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"id\">
+<metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">
+<dc:identifier id=\"id\">{}</dc:identifier>
+<dc:title>{}</dc:title>
+<dc:language>und</dc:language>
+<meta property=\"dcterms:modified\">{}T00:00:00Z</meta>
+</metadata>
+<manifest>
+<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>
+<item id=\"style\" href=\"style.css\" media-type=\"text/css\"/>
+{manifest}</manifest>
+<spine>
+{spine}</spine>
+</package>
+",
+            escape(&identifier),
+            escape(&self.title),
+            escape(&self.date),
+        )
+    }
+
+    /// The table of contents: each chapter, with its headings nested in it
+    /// by level.
+    fn nav(&self) -> String {
+        let mut entries = Vec::new();
+        for (i, doc) in self.chapters.iter().enumerate() {
+            let file = chapter_file(i);
+            let mut headings = doc
+                .lines
+                .iter()
+                .enumerate()
+                .filter_map(|(j, line)| match line {
+                    Line::Heading(level, s) => Some((*level, s.trim(), format!("{file}#h{j}"))),
+                    _ => None,
+                })
+                .peekable();
+            // the first heading names the chapter, so isn't listed again
+            headings.next_if(|(_, s, _)| *s == title(doc));
+            let children = nest(headings);
+            entries.push(TocEntry {
+                label: title(doc),
+                href: file,
+                children,
+            });
+        }
+
+        let mut out = String::new();
+        write_toc(&mut out, &entries);
+        xhtml_page(
+            &self.title,
+            &format!("<nav epub:type=\"toc\" id=\"toc\">\n<h1>Contents</h1>\n{out}</nav>\n"),
+        )
+    }
+
+    fn chapter_xhtml(&self, doc: &Document) -> String {
+        let files: HashMap<&Url, String> = self
+            .chapters
+            .iter()
+            .enumerate()
+            .map(|(i, doc)| (&doc.url, chapter_file(i)))
+            .collect();
+        let mut body = String::new();
+        write_body(&mut body, doc, &|url| {
+            let mut page = url.clone();
+            page.set_fragment(None);
+            match (files.get(&page), url.fragment()) {
+                (Some(file), Some(fragment)) => format!("{file}#{fragment}"),
+                (Some(file), None) => file.clone(),
+                (None, _) => url.to_string(),
+            }
+        });
+        xhtml_page(&title(doc), &body)
+    }
+}
+
+const CONTAINER: &str = "<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">
+<rootfiles>
+<rootfile full-path=\"OEBPS/content.opf\" media-type=\"application/oebps-package+xml\"/>
+</rootfiles>
+</container>
+";
+
+fn chapter_file(i: usize) -> String {
+    format!("chapter{}.xhtml", i + 1)
+}
+
+fn xhtml_page(title: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<!DOCTYPE html>
+<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\">
+<head>
+<title>{}</title>
+<link rel=\"stylesheet\" type=\"text/css\" href=\"style.css\"/>
+</head>
+<body>
+{body}</body>
+</html>
+",
+        escape(title)
+    )
+}
+
+struct TocEntry {
+    label: String,
+    href: String,
+    children: Vec<TocEntry>,
+}
+
+/// Nests each heading under the closest heading before it of a higher
+/// level.
+fn nest<'a>(headings: impl Iterator<Item = (usize, &'a str, String)>) -> Vec<TocEntry> {
+    let mut roots = Vec::new();
+    let mut open: Vec<(usize, TocEntry)> = Vec::new();
+    let close = |open: &mut Vec<(usize, TocEntry)>, roots: &mut Vec<TocEntry>| {
+        let (_, entry) = open.pop().expect("only called with open entries");
+        match open.last_mut() {
+            Some((_, parent)) => parent.children.push(entry),
+            None => roots.push(entry),
+        }
+    };
+    for (level, label, href) in headings {
+        while open.last().is_some_and(|(x, _)| *x >= level) {
+            close(&mut open, &mut roots);
+        }
+        let entry = TocEntry {
+            label: label.to_string(),
+            href,
+            children: Vec::new(),
+        };
+        open.push((level, entry));
+    }
+    while !open.is_empty() {
+        close(&mut open, &mut roots);
+    }
+    roots
+}
+
+fn write_toc(out: &mut String, entries: &[TocEntry]) {
+    out.push_str("<ol>\n");
+    for entry in entries {
+        let _ = write!(
+            out,
+            "<li><a href=\"{}\">{}</a>",
+            escape(&entry.href),
+            escape(&entry.label)
+        );
+        if !entry.children.is_empty() {
+            out.push('\n');
+            write_toc(out, &entry.children);
+        }
+        out.push_str("</li>\n");
+    }
+    out.push_str("</ol>\n");
+}
+
+/// Writes `doc`'s lines as HTML, which is also well-formed XHTML. Headings
+/// get an ID from their line number, and links lead to `href(url)`.
+fn write_body(out: &mut String, doc: &Document, href: &dyn Fn(&Url) -> String) {
+    let mut lines = doc.lines.iter().enumerate().peekable();
+    while let Some((i, line)) = lines.next() {
+        match line {
+            Line::Text(s) if s.trim().is_empty() => out.push_str("<p class=\"blank\"></p>\n"),
+            Line::Text(s) => {
+                let _ = writeln!(out, "<p>{}</p>", escape(s));
+            }
+            Line::Link(url, label) if !is_linkable(url) => {
+                let label = unlinked_label(url, label.as_deref());
+                let _ = writeln!(out, "<p class=\"link\">{}</p>", escape(&label));
+            }
+            Line::Link(url, label) => {
+                let label = label.clone().unwrap_or_else(|| url.to_string());
+                let _ = writeln!(
+                    out,
+                    "<p class=\"link\"><a href=\"{}\">{}</a></p>",
+                    escape(&href(url)),
+                    escape(&label)
+                );
+            }
+            Line::Heading(level, s) => {
+                let level = (*level).clamp(1, 3);
+                let _ = writeln!(out, "<h{level} id=\"h{i}\">{}</h{level}>", escape(s));
+            }
+            Line::List(s) => {
+                out.push_str("<ul>\n");
+                let _ = writeln!(out, "<li>{}</li>", escape(s));
+                while let Some((_, Line::List(s))) = lines.peek() {
+                    let _ = writeln!(out, "<li>{}</li>", escape(s));
+                    lines.next();
+                }
+                out.push_str("</ul>\n");
+            }
+            Line::Quote(s) => {
+                out.push_str("<blockquote>\n");
+                let _ = writeln!(out, "<p>{}</p>", escape(s));
+                while let Some((_, Line::Quote(s))) = lines.peek() {
+                    let _ = writeln!(out, "<p>{}</p>", escape(s));
+                    lines.next();
+                }
+                out.push_str("</blockquote>\n");
+            }
+            Line::PreFormatted(s, alt) => {
+                match alt {
+                    Some(alt) => {
+                        let _ = write!(out, "<pre title=\"{}\">", escape(alt));
+                    }
+                    None => out.push_str("<pre>"),
+                }
+                let _ = writeln!(out, "{}</pre>", escape(&ansi::strip(s)));
+            }
+            Line::Image(_) => out.push_str("<p>[image]</p>\n"),
+            Line::Toggle(_) => {}
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            // control characters aren't allowed in XHTML at all
+            '\t' | '\n' | '\r' => out.push(c),
+            c if c < ' ' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Whether links to `url` are kept as links. Anything but Gemini and the web,
+/// e.g. `javascript:`, is only written out as text.
+fn is_linkable(url: &Url) -> bool {
+    matches!(url.scheme(), "gemini" | "http" | "https")
+}
+
+/// What's written for a link that isn't kept as one: its label, if it has
+/// one, then its URL.
+fn unlinked_label(url: &Url, label: Option<&str>) -> String {
+    match label {
+        Some(label) => format!("{label} ({url})"),
+        None => url.to_string(),
+    }
+}
+
+/// Escapes the characters Markdown treats as inline markup, and anything at
+/// the start of `s` that would make it a heading, quote or list item.
+fn escape_markdown(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    if out.starts_with(['#', '-', '+']) {
+        out.insert(0, '\\');
+    }
+    // an ordered list item, e.g. `1. ` or `1) `
+    let digits = out.chars().take_while(char::is_ascii_digit).count();
+    if digits > 0 && out[digits..].starts_with(['.', ')']) {
+        out.insert(digits, '\\');
+    }
+    out
+}
+
+/// Wraps URLs Markdown would end early in angle brackets.
+fn markdown_url(url: &Url) -> String {
+    let url = url.as_str();
+    if url.contains(['(', ')', ' ']) {
+        format!("<{url}>")
+    } else {
+        url.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use zip::ZipArchive;
+
+    use super::*;
+
+    const FIXTURE: &str = include_str!("testdata/export.gmi");
+
+    fn fixture() -> Document {
+        let url = Url::parse("gemini://example.com/walks/coast.gmi").unwrap();
+        Document::parse(&url, FIXTURE)
+    }
+
+    #[test]
+    fn test_html() {
+        let html = to_html(&fixture());
+        assert!(html.starts_with("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n"));
+        assert!(html.contains("<title>A day out</title>"));
+        assert!(html.contains("<style>\nbody {"));
+        for fragment in [
+            "<h1 id=\"h0\">A day out</h1>",
+            "<p>Notes from a walk along the coast, with *asterisks* &amp; &lt;angle brackets&gt; left as written.</p>",
+            "<h2 id=\"h4\">Route</h2>\n<ul>\n<li>Harbour to the lighthouse</li>\n<li>Lighthouse to the cove</li>\n</ul>\n",
+            "<p class=\"link\"><a href=\"gemini://example.com/walks/map.gmi\">The route, mapped</a></p>",
+            "<a href=\"https://example.org/tides?date=2024-06-01\">Tide times (on the web)</a>",
+            "<a href=\"gemini://other.example/\">Somebody else&#39;s capsule</a>",
+            "<blockquote>\n<p>Grey seals, about a dozen.</p>\n<p>A kestrel.</p>\n</blockquote>\n",
+            "<pre title=\"ascii art of a wave\">  ~~~ `` ~~~\n</pre>",
+            "<p>1. not a list in gemtext</p>\n</body>\n</html>\n",
+        ] {
+            assert!(html.contains(fragment), "missing {fragment:?} in {html}");
+        }
+    }
+
+    #[test]
+    fn test_markdown() {
+        assert_eq!(
+            to_markdown(&fixture()),
+            "\
+# A day out
+
+Notes from a walk along the coast, with \\*asterisks\\* & \\<angle brackets\\> left as written.
+
+## Route
+
+* Harbour to the lighthouse
+* Lighthouse to the cove
+
+[The route, mapped](gemini://example.com/walks/map.gmi)
+
+[Photos](gemini://example.com/photos/)
+
+[Tide times (on the web)](https://example.org/tides?date=2024-06-01)
+
+[Somebody else's capsule](gemini://other.example/)
+
+### Things seen
+
+> Grey seals, about a dozen.
+
+> A kestrel.
+
+```ascii art of a wave
+  ~~~ `` ~~~
+```
+
+1\\. not a list in gemtext
+"
+        );
+    }
+
+    #[test]
+    fn test_unsafe_content() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/")?;
+        let doc = Document::parse(
+            &url,
+            "bell\x07 and\x1b[0m escape\ttab\n\
+             => javascript:alert(1) Click\n\
+             => data:text/html,<b>hi</b>\n",
+        );
+
+        let html = to_html(&doc);
+        assert!(html.contains("<p>bell and[0m escape\ttab</p>"));
+        assert!(html.contains("<p class=\"link\">Click (javascript:alert(1))</p>"));
+        assert!(html.contains("<p class=\"link\">data:text/html,&lt;b&gt;hi&lt;/b&gt;</p>"));
+        assert!(!html.contains("href=\"javascript:") && !html.contains("href=\"data:"));
+
+        let markdown = to_markdown(&doc);
+        assert!(markdown.contains("Click (javascript:alert(1))\n"));
+        assert!(!markdown.contains("](javascript:"));
+        Ok(())
+    }
+
+    // regression: backticks in the alt text ended the fence's info string
+    #[test]
+    fn test_markdown_fence_alt() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/")?;
+        let doc = Document::parse(&url, "```run `make`\n~~~~\n```\n```plain\n~\n```\n");
+        assert_eq!(
+            to_markdown(&doc),
+            "~~~~~run `make`\n~~~~\n~~~~~\n\n```plain\n~\n```\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_escape_markdown() {
+        assert_eq!(escape_markdown("# not a heading"), "\\# not a heading");
+        assert_eq!(escape_markdown("- not a list"), "\\- not a list");
+        assert_eq!(escape_markdown("2024) a year"), "2024\\) a year");
+        assert_eq!(escape_markdown("[a](b) `c`"), "\\[a\\](b) \\`c\\`");
+        assert_eq!(escape_markdown("100 ways"), "100 ways");
+    }
+
+    #[test]
+    fn test_capsule_links() -> anyhow::Result<()> {
+        let links = capsule_links(&fixture());
+        assert_eq!(
+            links,
+            [
+                Url::parse("gemini://example.com/walks/map.gmi")?,
+                Url::parse("gemini://example.com/photos/")?,
+            ]
+        );
+        Ok(())
+    }
+
+    fn read(archive: &mut ZipArchive<Cursor<Vec<u8>>>, name: &str) -> anyhow::Result<String> {
+        let mut s = String::new();
+        archive.by_name(name)?.read_to_string(&mut s)?;
+        Ok(s)
+    }
+
+    #[test]
+    fn test_epub() -> anyhow::Result<()> {
+        let map = Document::parse(
+            &Url::parse("gemini://example.com/walks/map.gmi")?,
+            "# The route\n## North\n### Cliffs\n## South\n=> coast.gmi#h4 Back\n",
+        );
+        let bytes = Epub::new("Walks", "2024-06-02")
+            .chapter(fixture())
+            .chapter(map)
+            .to_bytes()?;
+        let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+
+        let mimetype = archive.by_index(0)?;
+        assert_eq!(mimetype.name(), "mimetype");
+        assert_eq!(mimetype.compression(), CompressionMethod::Stored);
+        drop(mimetype);
+        assert_eq!(read(&mut archive, "mimetype")?, "application/epub+zip");
+        assert!(read(&mut archive, "META-INF/container.xml")?.contains("OEBPS/content.opf"));
+
+        let package = read(&mut archive, "OEBPS/content.opf")?;
+        assert!(package.contains("<dc:title>Walks</dc:title>"));
+        assert!(package.contains(
+            "<dc:identifier id=\"id\">gemini://example.com/walks/coast.gmi</dc:identifier>"
+        ));
+        assert!(package.contains("2024-06-02T00:00:00Z"));
+        assert!(package.contains(
+            "<spine>\n<itemref idref=\"chapter0\"/>\n<itemref idref=\"chapter1\"/>\n</spine>"
+        ));
+
+        let nav = read(&mut archive, "OEBPS/nav.xhtml")?;
+        assert!(nav.contains(
+            "<ol>
+<li><a href=\"chapter1.xhtml\">A day out</a>
+<ol>
+<li><a href=\"chapter1.xhtml#h4\">Route</a>
+<ol>
+<li><a href=\"chapter1.xhtml#h12\">Things seen</a></li>
+</ol>
+</li>
+</ol>
+</li>
+<li><a href=\"chapter2.xhtml\">The route</a>
+<ol>
+<li><a href=\"chapter2.xhtml#h1\">North</a>
+<ol>
+<li><a href=\"chapter2.xhtml#h2\">Cliffs</a></li>
+</ol>
+</li>
+<li><a href=\"chapter2.xhtml#h3\">South</a></li>
+</ol>
+</li>
+</ol>"
+        ));
+
+        // links between chapters stay inside the book
+        let first = read(&mut archive, "OEBPS/chapter1.xhtml")?;
+        assert!(first.starts_with("<?xml version=\"1.0\" encoding=\"utf-8\"?>"));
+        assert!(first.contains("<a href=\"chapter2.xhtml\">The route, mapped</a>"));
+        assert!(first.contains("<a href=\"gemini://example.com/photos/\">Photos</a>"));
+        let second = read(&mut archive, "OEBPS/chapter2.xhtml")?;
+        assert!(second.contains("<a href=\"chapter1.xhtml#h4\">Back</a>"));
+
+        assert!(Epub::new("Empty", "2024-06-02").to_bytes().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,479 @@
+//! Atom and RSS feeds, turned into a gemtext-like listing of their entries,
+//! and gemfeeds: gemtext pages linking to dated posts.
+
+use roxmltree::Node;
+use thiserror::Error;
+use url::Url;
+
+use crate::gemini::{
+    Status,
+    client::Client,
+    gemtext::{Document, Line},
+};
+
+#[derive(Error, Debug)]
+pub enum FeedError {
+    #[error("malformed feed: {0}")]
+    Xml(#[from] roxmltree::Error),
+    #[error("not an Atom or RSS feed: root element is `{0}`")]
+    UnknownRoot(String),
+    #[error("feed request failed: {0:?}: {1:?}")]
+    Status(Status, Option<String>),
+    #[error("not a feed: {0}")]
+    UnsupportedType(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Feed {
+    pub title: Option<String>,
+    pub subtitle: Option<String>,
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub title: String,
+    pub url: Option<Url>,
+    /// `YYYY-MM-DD`, so dates sort as strings.
+    pub date: Option<String>,
+    /// Plain text, with any HTML markup removed.
+    pub summary: Option<String>,
+}
+
+impl Feed {
+    /// Parses an Atom, RSS 2.0 or RSS 1.0 feed. Relative links are resolved
+    /// against `url`, the feed's own URL.
+    pub fn parse(url: &Url, s: &str) -> Result<Self, FeedError> {
+        let xml = roxmltree::Document::parse(s)?;
+        let root = xml.root_element();
+        match root.tag_name().name() {
+            "feed" => Ok(Self::parse_atom(url, root)),
+            "rss" => {
+                let channel = child(root, "channel");
+                Ok(Self::parse_rss(url, channel, channel))
+            }
+            // RSS 1.0 keeps the items next to the channel instead of in it
+            "RDF" => Ok(Self::parse_rss(url, child(root, "channel"), Some(root))),
+            x => Err(FeedError::UnknownRoot(x.to_string())),
+        }
+    }
+
+    /// Reads a gemfeed: a gemtext page whose links to posts are labelled
+    /// with the date they were published, e.g. `=> post.gmi 2024-03-01 Title`.
+    /// The first level 1 heading is the feed's title, and a level 2 heading
+    /// straight after it the subtitle.
+    pub fn from_gemfeed(doc: &Document) -> Self {
+        let title = doc
+            .lines
+            .iter()
+            .position(|x| matches!(x, Line::Heading(1, _)));
+        let subtitle = title
+            .and_then(|i| doc.lines[i + 1..].iter().find(|x| !is_blank(x)))
+            .and_then(|x| match x {
+                Line::Heading(2, s) => Some(s.clone()),
+                _ => None,
+            });
+
+        let entries = doc
+            .lines
+            .iter()
+            .filter_map(|line| {
+                let Line::Link(url, Some(label)) = line else {
+                    return None;
+                };
+                let date = rfc3339_date(label)?;
+                let title = label[date.len()..].trim_start_matches([' ', '\t', '-', ':', '–', '—']);
+                Some(Entry {
+                    title: title.to_string(),
+                    url: Some(url.clone()),
+                    date: Some(date),
+                    summary: None,
+                })
+            })
+            .collect();
+
+        Self {
+            title: title.and_then(|i| match &doc.lines[i] {
+                Line::Heading(_, s) => Some(s.clone()),
+                _ => None,
+            }),
+            subtitle,
+            entries,
+        }
+    }
+
+    /// Fetches `url` and reads it as a feed: Atom or RSS if it's served as
+    /// XML, otherwise as a gemfeed. A failure status is returned as
+    /// `FeedError::Status`, so the caller can tell when to slow down.
+    pub fn fetch(client: &Client, url: &Url) -> anyhow::Result<Self> {
+        let exchange = client.request(url, None)?;
+        let response = exchange.response;
+        if response.status != Status::Success {
+            return Err(FeedError::Status(response.status, response.ctx).into());
+        }
+
+        let mime: mime::Mime = response.ctx.as_deref().unwrap_or("text/gemini").parse()?;
+        let body = String::from_utf8(response.body.unwrap_or_default())?;
+        match mime.essence_str() {
+            "application/atom+xml" | "application/rss+xml" | "application/xml" | "text/xml" => {
+                Ok(Self::parse(&exchange.url, &body)?)
+            }
+            "text/gemini" => Ok(Self::from_gemfeed(&Document::parse(&exchange.url, &body))),
+            x => Err(FeedError::UnsupportedType(x.to_string()).into()),
+        }
+    }
+
+    fn parse_atom(url: &Url, feed: Node) -> Self {
+        let entries = children(feed, "entry")
+            .map(|entry| {
+                // the first link without a `rel` or with `rel="alternate"`
+                let link = children(entry, "link")
+                    .find(|x| x.attribute("rel").is_none_or(|rel| rel == "alternate"))
+                    .and_then(|x| x.attribute("href"));
+                let date = text(child(entry, "published"))
+                    .or_else(|| text(child(entry, "updated")))
+                    .and_then(|x| rfc3339_date(&x));
+                Entry {
+                    title: text(child(entry, "title")).unwrap_or_default(),
+                    url: link.and_then(|x| url.join(x).ok()),
+                    date,
+                    summary: text(child(entry, "summary"))
+                        .or_else(|| text(child(entry, "content")))
+                        .and_then(|x| strip_html(&x)),
+                }
+            })
+            .collect();
+
+        Self {
+            title: text(child(feed, "title")),
+            subtitle: text(child(feed, "subtitle")),
+            entries,
+        }
+    }
+
+    fn parse_rss(url: &Url, channel: Option<Node>, items: Option<Node>) -> Self {
+        let entries = items
+            .into_iter()
+            .flat_map(|x| children(x, "item"))
+            .map(|item| Entry {
+                title: text(child(item, "title")).unwrap_or_default(),
+                url: text(child(item, "link")).and_then(|x| url.join(&x).ok()),
+                date: text(child(item, "pubDate"))
+                    .and_then(|x| rfc2822_date(&x))
+                    // RSS 1.0 uses Dublin Core for dates
+                    .or_else(|| text(child(item, "date")).and_then(|x| rfc3339_date(&x))),
+                summary: text(child(item, "description")).and_then(|x| strip_html(&x)),
+            })
+            .collect();
+
+        Self {
+            title: channel.and_then(|x| text(child(x, "title"))),
+            subtitle: channel
+                .and_then(|x| text(child(x, "description")))
+                .and_then(|x| strip_html(&x)),
+            entries,
+        }
+    }
+
+    /// The feed as a page: its title as a heading, then a dated link to each
+    /// entry, followed by its summary.
+    pub fn to_document(&self, url: &Url) -> Document {
+        let mut lines = Vec::new();
+        lines.push(Line::Heading(
+            1,
+            self.title.clone().unwrap_or_else(|| url.to_string()),
+        ));
+        if let Some(subtitle) = &self.subtitle {
+            lines.push(Line::Text(subtitle.clone()));
+        }
+
+        for entry in &self.entries {
+            lines.push(Line::Text(String::new()));
+            let title = match &entry.date {
+                Some(date) if entry.title.is_empty() => date.clone(),
+                Some(date) => format!("{date} {}", entry.title),
+                None => entry.title.clone(),
+            };
+            match &entry.url {
+                Some(url) => lines.push(Line::Link(url.clone(), Some(title))),
+                None if !title.is_empty() => lines.push(Line::Heading(3, title)),
+                None => {}
+            }
+            if let Some(summary) = &entry.summary {
+                lines.push(Line::Text(summary.clone()));
+            }
+        }
+        Document::from_lines(url, lines)
+    }
+}
+
+fn is_blank(line: &Line) -> bool {
+    matches!(line, Line::Text(s) if s.trim().is_empty())
+}
+
+fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |x| x.is_element() && x.tag_name().name() == name)
+}
+
+/// The first child element called `name`, in any namespace.
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &'static str) -> Option<Node<'a, 'input>> {
+    children(node, name).next()
+}
+
+/// The trimmed text inside `node`, if there's any.
+fn text(node: Option<Node>) -> Option<String> {
+    let text: String = node?
+        .descendants()
+        .filter(Node::is_text)
+        .filter_map(|x| x.text())
+        .collect();
+    Some(text.trim().to_string()).filter(|x| !x.is_empty())
+}
+
+/// Removes the tags from an HTML fragment, decodes the common entities and
+/// collapses whitespace.
+fn strip_html(s: &str) -> Option<String> {
+    const INLINE: [&str; 12] = [
+        "a", "abbr", "b", "code", "em", "i", "s", "small", "span", "strong", "sub", "sup",
+    ];
+    let mut text = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        let tag = rest[start + 1..].trim_start_matches('/');
+        let name = &tag[..tag
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(tag.len())];
+        // keep the words either side of a block element apart
+        if !INLINE.contains(&name.to_ascii_lowercase().as_str()) {
+            text.push(' ');
+        }
+        rest = rest[start..]
+            .find('>')
+            .map_or("", |end| &rest[start + end + 1..]);
+    }
+    text.push_str(rest);
+
+    let text = text
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&");
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    Some(text).filter(|x| !x.is_empty())
+}
+
+/// The date part of an RFC 3339 timestamp, e.g. `2024-03-01T12:00:00Z`.
+fn rfc3339_date(s: &str) -> Option<String> {
+    let date = s.get(..10)?;
+    let valid = date.char_indices().all(|(i, c)| match i {
+        4 | 7 => c == '-',
+        _ => c.is_ascii_digit(),
+    });
+    valid.then(|| date.to_string())
+}
+
+/// The date part of an RFC 2822 timestamp, e.g. `Fri, 01 Mar 2024 12:00:00 GMT`.
+fn rfc2822_date(s: &str) -> Option<String> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    // the day of the week is optional
+    let s = s.split_once(',').map_or(s, |(_, x)| x);
+    let mut words = s.split_whitespace();
+    let day: u8 = words.next()?.parse().ok()?;
+    let month = words.next()?.get(..3)?.to_ascii_lowercase();
+    let month = MONTHS.iter().position(|x| *x == month)? + 1;
+    let year: u16 = words.next()?.parse().ok()?;
+    if !(1..=31).contains(&day) {
+        return None;
+    }
+    // two digit years are from the obsolete syntax
+    let year = match year {
+        0..50 => year + 2000,
+        50..100 => year + 1900,
+        _ => year,
+    };
+    Some(format!("{year:04}-{month:02}-{day:02}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ATOM: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Example gemlog</title>
+  <subtitle>Thoughts &amp; things</subtitle>
+  <link href="gemini://example.com/gemlog/"/>
+  <entry>
+    <title>Second post</title>
+    <link rel="self" href="gemini://example.com/feed.xml"/>
+    <link href="2024-03-01-second.gmi"/>
+    <updated>2024-03-02T09:00:00Z</updated>
+    <published>2024-03-01T12:00:00+01:00</published>
+    <summary type="html">&lt;p&gt;Some &lt;b&gt;bold&lt;/b&gt;
+      text&lt;/p&gt;</summary>
+  </entry>
+  <entry>
+    <title>First post</title>
+    <link rel="alternate" href="gemini://example.com/gemlog/first.gmi"/>
+    <updated>2024-01-15T00:00:00Z</updated>
+  </entry>
+</feed>"#;
+
+    const RSS: &str = r#"<?xml version="1.0"?>
+<rss version="2.0">
+  <channel>
+    <title>Example news</title>
+    <description>News from example.com</description>
+    <item>
+      <title>Launch</title>
+      <link>gemini://example.com/news/launch.gmi</link>
+      <pubDate>Tue, 05 Mar 2024 08:30:00 GMT</pubDate>
+      <description><![CDATA[We <i>launched</i>.]]></description>
+    </item>
+    <item>
+      <description>An item without a title or link</description>
+    </item>
+  </channel>
+</rss>"#;
+
+    #[test]
+    fn test_parse_atom() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/gemlog/atom.xml")?;
+        let feed = Feed::parse(&url, ATOM)?;
+        assert_eq!(feed.title.as_deref(), Some("Example gemlog"));
+        assert_eq!(feed.subtitle.as_deref(), Some("Thoughts & things"));
+        assert_eq!(
+            feed.entries,
+            [
+                Entry {
+                    title: "Second post".into(),
+                    url: Some(url.join("2024-03-01-second.gmi")?),
+                    date: Some("2024-03-01".into()),
+                    summary: Some("Some bold text".into()),
+                },
+                Entry {
+                    title: "First post".into(),
+                    url: Some(url.join("first.gmi")?),
+                    date: Some("2024-01-15".into()),
+                    summary: None,
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_rss() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/news/rss.xml")?;
+        let feed = Feed::parse(&url, RSS)?;
+        assert_eq!(feed.title.as_deref(), Some("Example news"));
+        assert_eq!(feed.entries.len(), 2);
+        assert_eq!(feed.entries[0].url, Some(url.join("launch.gmi")?));
+        assert_eq!(feed.entries[0].date.as_deref(), Some("2024-03-05"));
+        assert_eq!(feed.entries[0].summary.as_deref(), Some("We launched."));
+        assert_eq!(feed.entries[1].title, "");
+        assert_eq!(feed.entries[1].url, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_document() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/news/rss.xml")?;
+        let doc = Feed::parse(&url, RSS)?.to_document(&url);
+        assert_eq!(
+            doc.lines,
+            [
+                Line::Heading(1, "Example news".into()),
+                Line::Text("News from example.com".into()),
+                Line::Text(String::new()),
+                Line::Link(url.join("launch.gmi")?, Some("2024-03-05 Launch".into())),
+                Line::Text("We launched.".into()),
+                Line::Text(String::new()),
+                Line::Text("An item without a title or link".into()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_gemfeed() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/gemlog/")?;
+        let doc = Document::parse(
+            &url,
+            "# Example gemlog\n\
+             \n\
+             ## Thoughts and things\n\
+             => /about.gmi About me\n\
+             => 2024-03-01-second.gmi 2024-03-01 - Second post\n\
+             => first.gmi 2024-01-15 First post\n\
+             => undated.gmi 2024 in review\n",
+        );
+        let feed = Feed::from_gemfeed(&doc);
+        assert_eq!(feed.title.as_deref(), Some("Example gemlog"));
+        assert_eq!(feed.subtitle.as_deref(), Some("Thoughts and things"));
+        assert_eq!(
+            feed.entries,
+            [
+                Entry {
+                    title: "Second post".into(),
+                    url: Some(url.join("2024-03-01-second.gmi")?),
+                    date: Some("2024-03-01".into()),
+                    summary: None,
+                },
+                Entry {
+                    title: "First post".into(),
+                    url: Some(url.join("first.gmi")?),
+                    date: Some("2024-01-15".into()),
+                    summary: None,
+                },
+            ]
+        );
+
+        let feed = Feed::from_gemfeed(&Document::parse(&url, "=> a.gmi 2024-01-01\n"));
+        assert_eq!(feed.title, None);
+        assert_eq!(feed.entries[0].title, "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/")?;
+        assert!(matches!(
+            Feed::parse(&url, "<html></html>"),
+            Err(FeedError::UnknownRoot(x)) if x == "html"
+        ));
+        assert!(matches!(
+            Feed::parse(&url, "<feed>"),
+            Err(FeedError::Xml(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_dates() {
+        assert_eq!(
+            rfc3339_date("2024-03-01T12:00:00Z").as_deref(),
+            Some("2024-03-01")
+        );
+        assert_eq!(rfc3339_date("2024-3-1"), None);
+        assert_eq!(
+            rfc2822_date("Fri, 1 Mar 2024 12:00:00 +0000").as_deref(),
+            Some("2024-03-01")
+        );
+        assert_eq!(
+            rfc2822_date("01 March 99 12:00 EST").as_deref(),
+            Some("1999-03-01")
+        );
+        assert_eq!(rfc2822_date("Someday"), None);
+    }
+}
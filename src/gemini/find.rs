@@ -0,0 +1,161 @@
+//! Finding text in a document, as it's shown on the page.
+
+use std::{borrow::Cow, ops::Range};
+
+use fancy_regex::{Regex, RegexBuilder};
+use thiserror::Error;
+
+use crate::gemini::{
+    ansi,
+    gemtext::{Document, Line},
+};
+
+#[derive(Error, Debug)]
+pub enum FindError {
+    #[error("invalid pattern: {0}")]
+    InvalidPattern(#[source] Box<fancy_regex::Error>),
+    #[error("search failed: {0}")]
+    Search(#[source] Box<fancy_regex::Error>),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    pub text: String,
+    pub case_sensitive: bool,
+    pub regex: bool, // whether `text` is a regular expression
+}
+
+/// Where some text was found: the byte range in the text of a line, as
+/// given by [`line_text`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    pub line: usize,
+    pub range: Range<usize>,
+}
+
+/// The text shown for `line`: a link's label, or its URL if it has none,
+/// and preformatted text without ANSI escapes. Images have none.
+pub fn line_text(line: &Line) -> Option<Cow<'_, str>> {
+    match line {
+        Line::Text(s) | Line::Heading(_, s) | Line::List(s) | Line::Quote(s) => {
+            Some(Cow::Borrowed(s))
+        }
+        Line::Link(url, label) => Some(Cow::Borrowed(label.as_deref().unwrap_or(url.as_str()))),
+        Line::PreFormatted(s, _) if ansi::has_escapes(s) => Some(Cow::Owned(ansi::strip(s))),
+        Line::PreFormatted(s, _) => Some(Cow::Borrowed(s)),
+        // the parser folds toggles into the preformatted block they open
+        Line::Toggle(_) | Line::Image(_) => None,
+    }
+}
+
+/// Every match of `query` in `doc`, in order. An empty query matches
+/// nothing, as do empty matches of a regular expression.
+pub fn find(doc: &Document, query: &Query) -> Result<Vec<Match>, FindError> {
+    if query.text.is_empty() {
+        return Ok(Vec::new());
+    }
+    let regex = query.to_regex()?;
+    let mut matches = Vec::new();
+    for (i, line) in doc.lines.iter().enumerate() {
+        let Some(text) = line_text(line) else {
+            continue;
+        };
+        for found in regex.find_iter(&text) {
+            let found = found.map_err(|e| FindError::Search(Box::new(e)))?;
+            if !found.range().is_empty() {
+                matches.push(Match {
+                    line: i,
+                    range: found.range(),
+                });
+            }
+        }
+    }
+    Ok(matches)
+}
+
+impl Query {
+    fn to_regex(&self) -> Result<Regex, FindError> {
+        let pattern = if self.regex {
+            Cow::Borrowed(self.text.as_str())
+        } else {
+            fancy_regex::escape(&self.text)
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .build()
+            .map_err(|e| FindError::InvalidPattern(Box::new(e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::*;
+
+    fn fixture() -> anyhow::Result<Document> {
+        let url = Url::parse("gemini://example.com/")?;
+        Ok(Document::parse(
+            &url,
+            "# Cats\nA cat (or two).\n=> /cats.gmi More cats\n=> /dogs.gmi\n```\n\u{1b}[31mcat\u{1b}[0m\n```\n* CAT\n",
+        ))
+    }
+
+    fn find_all(doc: &Document, text: &str, case_sensitive: bool, regex: bool) -> Vec<Match> {
+        let query = Query {
+            text: text.to_string(),
+            case_sensitive,
+            regex,
+        };
+        find(doc, &query).unwrap()
+    }
+
+    fn at(line: usize, range: Range<usize>) -> Match {
+        Match { line, range }
+    }
+
+    #[test]
+    fn test_find() -> anyhow::Result<()> {
+        let doc = fixture()?;
+        assert_eq!(
+            find_all(&doc, "cat", false, false),
+            [
+                at(0, 0..3),
+                at(1, 2..5),
+                at(2, 5..8),
+                at(4, 0..3),
+                at(5, 0..3)
+            ]
+        );
+        assert_eq!(
+            find_all(&doc, "cat", true, false),
+            [at(1, 2..5), at(2, 5..8), at(4, 0..3)]
+        );
+        // unlabelled links are searched by URL
+        assert_eq!(find_all(&doc, "dogs", false, false), [at(3, 21..25)]);
+        // the text isn't a pattern unless asked
+        assert_eq!(find_all(&doc, "(or", false, false), [at(1, 6..9)]);
+        assert!(find_all(&doc, "", false, false).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_regex() -> anyhow::Result<()> {
+        let doc = fixture()?;
+        assert_eq!(find_all(&doc, r"^[A-Z]+$", true, true), [at(5, 0..3)]);
+        assert_eq!(find_all(&doc, r"\(.*\)", false, true), [at(1, 6..14)]);
+        // empty matches are left out
+        assert!(find_all(&doc, "z*", false, true).is_empty());
+
+        let query = Query {
+            text: "(unclosed".into(),
+            case_sensitive: false,
+            regex: true,
+        };
+        assert!(matches!(
+            find(&doc, &query),
+            Err(FindError::InvalidPattern(_))
+        ));
+        Ok(())
+    }
+}
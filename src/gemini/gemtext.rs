@@ -1,85 +1,108 @@
-use iced::{
-    Element, Font, padding,
-    widget::{Column, Container},
-};
-use std::fmt::Write;
+use std::sync::Arc;
 use thiserror::Error;
 use url::Url;
 
-use crate::ui::{gemini_link::GeminiLink, gemini_text::GeminiText};
+/// A zero-width space, written before text that would otherwise be read as
+/// another kind of line, as gemtext has no escapes. Being invisible, the line
+/// still looks the same, and it's dropped again when the line is parsed.
+const ESCAPE: char = '\u{200B}';
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Document {
     pub lines: Vec<Line>,
     pub url: Url,
+    /// The language of the document's preformatted text, e.g. the
+    /// `text/x-rust` of a plain text response, for highlighting it.
+    pub language: Option<String>,
 }
 
 impl Document {
-    pub fn view<'a, Message: Clone + 'a>(
-        &'a self,
-        on_press_link: fn(&Url) -> Message,
-    ) -> Element<'a, Message> {
-        Column::from_vec(
-            self.lines
-                .iter()
-                .map(|line| line.view(on_press_link))
-                .collect(),
-        )
-        .into()
-    }
-
-    pub fn from_lines<'a>(url: &Url, iter: impl IntoIterator<Item = Line>) -> Self {
+    pub fn from_lines(url: &Url, iter: impl IntoIterator<Item = Line>) -> Self {
         Document {
             lines: iter.into_iter().collect(),
             url: url.clone(),
+            language: None,
         }
     }
-    pub fn parse(url: &Url, doc: &str) -> anyhow::Result<Self> {
-        let mut state = ParserMode::Normal;
+
+    /// Parses a `text/gemini` document as described in the Gemini
+    /// specification. This never fails: lines that can't be parsed as what
+    /// they look like, e.g. a link to an invalid URL, are kept as text.
+    pub fn parse(url: &Url, doc: &str) -> Self {
         let mut lines = Vec::new();
-        let mut doc = doc;
-        let mut acc = String::new();
-        while let Some((line, rest)) = doc.split_once('\n') {
-            if state == ParserMode::Normal {
-                let l = Line::parse(url, line)?;
-                if let Line::Toggle(s) = l {
-                    log::info!("Document::parse: read toggle: {s}");
-                    state = if state == ParserMode::Normal {
-                        acc = String::new();
-                        ParserMode::PreFormatted
-                    } else {
-                        ParserMode::Normal
-                    }
+        // the alt text and contents of the preformatted block we're in, if any
+        let mut preformatted: Option<(Option<String>, String)> = None;
+
+        // `lines` splits on both LF and CRLF, and doesn't need a final newline
+        for line in doc.lines() {
+            if let Some((alt, text)) = &mut preformatted {
+                if line.starts_with("```") {
+                    lines.push(Line::PreFormatted(std::mem::take(text), alt.take()));
+                    preformatted = None;
                 } else {
-                    lines.push(l);
+                    let line = match line.strip_prefix(ESCAPE) {
+                        Some(rest) if Line::is_fence(rest) => rest,
+                        _ => line,
+                    };
+                    text.push_str(line);
+                    text.push('\n');
                 }
-            } else if line.starts_with("```") {
-                lines.push(Line::PreFormatted(acc.clone()));
-                state = ParserMode::Normal;
-            } else {
-                writeln!(acc, "{line}")?;
+                continue;
             }
 
-            doc = rest;
+            match Line::parse(url, line) {
+                Ok(Line::Toggle(alt)) => {
+                    log::trace!("Document::parse: read toggle: {alt}");
+                    let alt = Some(alt.trim().to_string()).filter(|x| !x.is_empty());
+                    preformatted = Some((alt, String::new()));
+                }
+                Ok(l) => lines.push(l),
+                Err(e) => {
+                    log::warn!("Document::parse: {e}, keeping {line:?} as text");
+                    lines.push(Line::Text(line.to_string()));
+                }
+            }
+        }
+
+        // an unterminated preformatted block runs to the end of the document
+        if let Some((alt, text)) = preformatted {
+            lines.push(Line::PreFormatted(text, alt));
         }
 
-        Ok(Document {
+        Document {
             lines,
             url: url.clone(),
-        })
+            language: None,
+        }
+    }
+
+    /// Serializes the document back into canonical `text/gemini`, with
+    /// links made relative to the document's URL where possible. Parsing the
+    /// output gives back the same document, except for what gemtext has no
+    /// way to express: images and stray toggle lines are left out, carriage
+    /// returns at the end of a line are dropped, and newlines within one
+    /// become spaces. Text that would be read as another kind of line, and
+    /// lines of preformatted text that would end the block, keep their
+    /// meaning by starting with an invisible zero-width space.
+    pub fn to_gemtext(&self) -> String {
+        let mut out = String::new();
+        for line in &self.lines {
+            line.write_gemtext(&self.url, &mut out);
+        }
+        out
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Line {
     Text(String),
     Link(Url, Option<String>), // URL, friendly name
-    Heading(usize, String),
+    Heading(usize, String),    // level (1 to 3), text
     List(String),
     Quote(String),
-    Toggle(String),
-    PreFormatted(String),
-    Image(iced::advanced::image::Handle),
+    Toggle(String),                       // alt text
+    PreFormatted(String, Option<String>), // contents, alt text
+    Image(Arc<[u8]>),                     // encoded image data
 }
 
 #[derive(Error, Debug)]
@@ -88,49 +111,30 @@ pub enum LineParsingError {
     MissingUri,
 }
 impl Line {
-    pub fn view<'a, Message: Clone + 'a>(
-        &'a self,
-        on_press_link: fn(&Url) -> Message,
-    ) -> Element<'a, Message> {
-        let sizes = [40, 30, 20];
-        match self {
-            Line::Text(s) => GeminiText::new(s).view(),
-            Line::Link(url, friendly) => {
-                GeminiLink::new(url.clone(), friendly.clone(), on_press_link).view()
-            }
-            Line::Heading(level, s) => GeminiText::new(s).size(sizes[*level - 1]).view(),
-            Line::List(s) => GeminiText::new(&format!(" • {s}")).view(),
-            Line::Quote(s) => {
-                Element::new(Container::new(GeminiText::new(s).view()).padding(padding::left(10)))
-            }
-            Line::PreFormatted(s) => GeminiText::new(s).font(Font::MONOSPACE).view(),
-            Line::Toggle(_) => unreachable!(),
-            Line::Image(handle) => Element::new(iced::widget::image(handle)),
-        }
-    }
-
     fn parse_link(current_url: &Url, line: &str) -> anyhow::Result<Self> {
-        let line = &line[2..]; // we don't care about the =>
-        let mut spl = line.trim().splitn(2, char::is_whitespace);
-        let uri = spl
-            .next()
-            .ok_or(LineParsingError::MissingUri)?
-            .trim()
-            .to_string();
+        let line = line[2..].trim(); // we don't care about the =>
+        let (uri, friendly) = match line.split_once(char::is_whitespace) {
+            Some((uri, friendly)) => (uri, Some(friendly.trim())),
+            None => (line, None),
+        };
+        if uri.is_empty() {
+            return Err(LineParsingError::MissingUri.into());
+        }
 
-        let friendly = spl.next().map(|x| x.trim().to_string());
-        log::info!("{uri} {friendly:?}");
-        Ok(Line::Link(current_url.join(&uri)?, friendly))
+        let friendly = friendly.filter(|x| !x.is_empty()).map(str::to_string);
+        log::trace!("{uri} {friendly:?}");
+        Ok(Line::Link(current_url.join(uri)?, friendly))
     }
 
     fn parse_header(line: &str) -> Self {
-        let hashes = line.chars().take_while(|&c| c == '#').count();
-        let rest = line[hashes..].trim_start();
-        Line::Heading(hashes, rest.to_string())
+        // only `#`, `##` and `###` are headings, any further `#`s are part of the text
+        let level = line.chars().take_while(|&c| c == '#').count().min(3);
+        let rest = line[level..].trim_start();
+        Line::Heading(level, rest.to_string())
     }
 
     fn parse_list(line: &str) -> Self {
-        let line = line[1..].trim(); // everything after the `*`
+        let line = line[2..].trim(); // everything after the `* `
         Line::List(line.to_string())
     }
 
@@ -143,28 +147,97 @@ impl Line {
         Line::Quote(line.to_string())
     }
 
+    /// The shortest form of `url` that resolves back to it from `base`.
+    fn relative_link(base: &Url, url: &Url) -> String {
+        match base.make_relative(url) {
+            Some(relative) if !relative.is_empty() && base.join(&relative).as_ref() == Ok(url) => {
+                relative
+            }
+            _ => url.to_string(),
+        }
+    }
+
+    fn write_gemtext(&self, base: &Url, out: &mut String) {
+        fn push_line(out: &mut String, line: &str) {
+            // a trailing \r would be read back as part of a CRLF line ending,
+            // and a newline would start another line
+            out.push_str(&line.trim_end_matches('\r').replace('\n', " "));
+            out.push('\n');
+        }
+
+        match self {
+            Line::Text(s) if Line::has_line_type(s) => push_line(out, &format!("{ESCAPE}{s}")),
+            Line::Text(s) => push_line(out, s),
+            Line::Link(url, friendly) => {
+                let link = Line::relative_link(base, url);
+                match friendly {
+                    Some(friendly) => push_line(out, &format!("=> {link} {friendly}")),
+                    None => push_line(out, &format!("=> {link}")),
+                }
+            }
+            Line::Heading(level, s) => {
+                push_line(out, &format!("{} {s}", "#".repeat((*level).clamp(1, 3))));
+            }
+            Line::List(s) => push_line(out, &format!("* {s}")),
+            Line::Quote(s) if s.is_empty() => push_line(out, ">"),
+            Line::Quote(s) => push_line(out, &format!("> {s}")),
+            Line::PreFormatted(text, alt) => {
+                push_line(out, &format!("```{}", alt.as_deref().unwrap_or_default()));
+                for line in text.lines() {
+                    if Line::is_fence(line) {
+                        // it would end the block
+                        push_line(out, &format!("{ESCAPE}{line}"));
+                    } else {
+                        push_line(out, line);
+                    }
+                }
+                push_line(out, "```");
+            }
+            // neither has a gemtext representation
+            Line::Toggle(_) | Line::Image(_) => {}
+        }
+    }
+
+    /// Whether a line of `s` would be read as something other than text once
+    /// any zero-width spaces it starts with are dropped. Those are written
+    /// with one more in front, so that one can be dropped again when reading.
+    fn has_line_type(s: &str) -> bool {
+        let s = s.trim_start_matches(ESCAPE);
+        ["=>", "#", "* ", "```", ">"]
+            .iter()
+            .any(|prefix| s.starts_with(prefix))
+    }
+
+    /// Like `has_line_type`, for a line of preformatted text that would end
+    /// the block.
+    fn is_fence(s: &str) -> bool {
+        s.trim_start_matches(ESCAPE).starts_with("```")
+    }
+
     pub fn parse(current_url: &Url, line: &str) -> anyhow::Result<Self> {
         log::trace!("Line: parsing {line}");
         match line {
             x if x.starts_with("=>") => Line::parse_link(current_url, line),
             x if x.starts_with('#') => Ok(Line::parse_header(line)),
-            x if x.starts_with('*') => Ok(Line::parse_list(line)),
+            x if x.starts_with("* ") => Ok(Line::parse_list(line)),
             x if x.starts_with("```") => Ok(Line::parse_toggle(line)),
             x if x.starts_with('>') => Ok(Line::parse_quote(line)),
-            x => Ok(Line::Text(x.to_string())),
+            x => match x.strip_prefix(ESCAPE) {
+                // written by `write_gemtext` to keep the rest from being read
+                // as another kind of line
+                Some(rest) if Line::has_line_type(rest) => Ok(Line::Text(rest.to_string())),
+                _ => Ok(Line::Text(x.to_string())),
+            },
         }
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
-enum ParserMode {
-    Normal,
-    PreFormatted,
-}
-
 #[cfg(test)]
 mod tests {
+    use proptest::prelude::*;
+
     use super::*;
+    use crate::gemini::{html, markdown};
 
     #[test]
     fn test_parse_quote() -> anyhow::Result<()> {
@@ -217,9 +290,10 @@ mod tests {
     #[test]
     fn test_parse_list_no_space() -> anyhow::Result<()> {
         let url = Url::parse("gemini://example.com/")?;
+        // the spec requires a space after the `*`
         let x = Line::parse(&url, "*hello")?;
-        if let Line::List(q) = x {
-            assert_eq!(q, "hello");
+        if let Line::Text(q) = x {
+            assert_eq!(q, "*hello");
         } else {
             panic!()
         }
@@ -230,6 +304,13 @@ mod tests {
     fn test_parse_list_no_text() -> anyhow::Result<()> {
         let url = Url::parse("gemini://example.com/")?;
         let x = Line::parse(&url, "*")?;
+        if let Line::Text(q) = x {
+            assert_eq!(q, "*");
+        } else {
+            panic!()
+        }
+
+        let x = Line::parse(&url, "* ")?;
         if let Line::List(q) = x {
             assert_eq!(q, "");
         } else {
@@ -403,4 +484,314 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_parse_header_levels() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/")?;
+        let x = Line::parse(&url, "#### hello")?;
+        if let Line::Heading(level, s) = x {
+            assert_eq!(level, 3);
+            assert_eq!(s, "# hello");
+        } else {
+            panic!();
+        }
+
+        let x = Line::parse(&url, "#")?;
+        if let Line::Heading(level, s) = x {
+            assert_eq!(level, 1);
+            assert_eq!(s, "");
+        } else {
+            panic!();
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_link_whitespace() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/")?;
+        let target = Url::parse("gemini://example.com/abc")?;
+        let x = Line::parse(&url, "=>abc\t  tabbed name ")?;
+        if let Line::Link(link_url, friendly) = x {
+            assert_eq!(link_url, target);
+            assert_eq!(friendly.as_deref(), Some("tabbed name"));
+        } else {
+            panic!();
+        }
+
+        let x = Line::parse(&url, "=> abc   ")?;
+        if let Line::Link(link_url, friendly) = x {
+            assert_eq!(link_url, target);
+            assert_eq!(friendly, None);
+        } else {
+            panic!();
+        }
+
+        assert!(Line::parse(&url, "=>").is_err());
+        assert!(Line::parse(&url, "=>   ").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_document() -> anyhow::Result<()> {
+        // the example document from the gemtext specification
+        let url = Url::parse("gemini://example.org/")?;
+        let doc = Document::parse(
+            &url,
+            "# Project Gemini\r\n\
+             \r\n\
+             ## Overview\r\n\
+             \r\n\
+             Gemini is a new internet protocol which:\r\n\
+             \r\n\
+             * Is heavier than gopher\r\n\
+             * Is lighter than the web\r\n\
+             \r\n\
+             => gemini://example.org/docs/ Documentation\r\n\
+             > Quoted text\r\n\
+             ```rust code\r\n\
+             fn main() {}\r\n\
+             \x20   indented\r\n\
+             ``` closing alt text is ignored\r\n\
+             last line without a newline",
+        );
+
+        assert!(matches!(
+            &doc.lines[..],
+            [
+                Line::Heading(1, h1),
+                Line::Text(_),
+                Line::Heading(2, h2),
+                Line::Text(_),
+                Line::Text(text),
+                Line::Text(_),
+                Line::List(item1),
+                Line::List(item2),
+                Line::Text(_),
+                Line::Link(link, Some(name)),
+                Line::Quote(quote),
+                Line::PreFormatted(pre, Some(alt)),
+                Line::Text(last),
+            ] if h1 == "Project Gemini"
+                && h2 == "Overview"
+                && text == "Gemini is a new internet protocol which:"
+                && item1 == "Is heavier than gopher"
+                && item2 == "Is lighter than the web"
+                && link.as_str() == "gemini://example.org/docs/"
+                && name == "Documentation"
+                && quote == "Quoted text"
+                && pre == "fn main() {}\n    indented\n"
+                && alt == "rust code"
+                && last == "last line without a newline"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_preformatted() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/")?;
+
+        // toggles without alt text, with gemtext syntax inside the block
+        let doc = Document::parse(&url, "```\n# not a heading\n=> not a link\n```\n");
+        assert!(matches!(
+            &doc.lines[..],
+            [Line::PreFormatted(pre, None)] if pre == "# not a heading\n=> not a link\n"
+        ));
+
+        // an unterminated block runs to the end of the document
+        let doc = Document::parse(&url, "text\n```  ascii art \n /\\_/\\");
+        assert!(matches!(
+            &doc.lines[..],
+            [Line::Text(_), Line::PreFormatted(pre, Some(alt))]
+                if pre == " /\\_/\\\n" && alt == "ascii art"
+        ));
+
+        let doc = Document::parse(&url, "```\n```");
+        assert!(matches!(&doc.lines[..], [Line::PreFormatted(pre, None)] if pre.is_empty()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_malformed() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/")?;
+        let doc = Document::parse(&url, "=>\n=> http://[invalid\n\n\r\n");
+        assert!(matches!(
+            &doc.lines[..],
+            [Line::Text(a), Line::Text(b), Line::Text(c), Line::Text(d)]
+                if a == "=>" && b == "=> http://[invalid" && c.is_empty() && d.is_empty()
+        ));
+
+        assert!(Document::parse(&url, "").lines.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_gemtext() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/docs/index.gmi")?;
+        let doc = Document::from_lines(
+            &url,
+            [
+                Line::Heading(1, "Docs".into()),
+                Line::Text(String::new()),
+                Line::Link(Url::parse("gemini://example.com/docs/spec.gmi")?, None),
+                Line::Link(
+                    Url::parse("gemini://example.com/about")?,
+                    Some("About".into()),
+                ),
+                Line::Link(Url::parse("gemini://other.example/")?, Some("Other".into())),
+                Line::Link(url.clone(), Some("This page".into())),
+                Line::List("item".into()),
+                Line::Quote(String::new()),
+                Line::PreFormatted("  art\n".into(), Some("alt text".into())),
+                Line::Image(Arc::from([1, 2, 3])),
+            ],
+        );
+        assert_eq!(
+            doc.to_gemtext(),
+            "# Docs\n\
+             \n\
+             => spec.gmi\n\
+             => ../about About\n\
+             => gemini://other.example/ Other\n\
+             => gemini://example.com/docs/index.gmi This page\n\
+             * item\n\
+             >\n\
+             ```alt text\n  \
+             art\n\
+             ```\n"
+        );
+
+        let mut reparsed = Document::parse(&url, &doc.to_gemtext());
+        reparsed.lines.push(Line::Image(Arc::from([1, 2, 3])));
+        assert_eq!(reparsed, doc);
+        Ok(())
+    }
+
+    fn gemtext_line() -> impl Strategy<Value = String> {
+        "(=>|#{1,4}|\\* ?|>|```)?[ \t\r#*>=`a-z/:.?\\[]{0,16}"
+    }
+
+    fn gemtext() -> impl Strategy<Value = String> {
+        proptest::collection::vec((gemtext_line(), prop_oneof!["\n", "\r\n"]), 0..16)
+            .prop_map(|lines| lines.into_iter().map(|(l, end)| l + &end).collect())
+    }
+
+    proptest! {
+        #[test]
+        fn test_to_gemtext_idempotent(doc in prop_oneof![gemtext(), any::<String>()]) {
+            let url = Url::parse("gemini://example.com/a/b").unwrap();
+            let parsed = Document::parse(&url, &doc);
+            let serialized = parsed.to_gemtext();
+            let reparsed = Document::parse(&url, &serialized);
+            prop_assert_eq!(reparsed.to_gemtext(), serialized);
+            prop_assert_eq!(reparsed.lines.len(), parsed.lines.len());
+        }
+
+        #[test]
+        fn test_parse_line_never_panics(line in any::<String>()) {
+            let url = Url::parse("gemini://example.com/a/b").unwrap();
+            if let Ok(Line::Heading(level, _)) = Line::parse(&url, &line) {
+                prop_assert!((1..=3).contains(&level));
+            }
+        }
+
+        #[test]
+        fn test_relative_link_keeps_scheme(uri in "[a-zA-Z0-9/._?#%~-]{1,32}") {
+            // without a `:`, `uri` can't name a scheme, so it must resolve
+            // against the document's
+            let url = Url::parse("gemini://example.com/a/b").unwrap();
+            if let Ok(Line::Link(target, _)) = Line::parse(&url, &format!("=> {uri}")) {
+                prop_assert_eq!(target.scheme(), "gemini");
+            }
+        }
+    }
+
+    // regression: text that looked like another kind of line, and fences in
+    // preformatted text, were written as they were and read back differently
+    #[test]
+    fn test_to_gemtext_escapes() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/")?;
+        let doc = Document::from_lines(
+            &url,
+            [
+                Line::Text("# not a heading".into()),
+                Line::Text("=> not a link".into()),
+                Line::Text("* not an item".into()),
+                Line::Text("> not a quote".into()),
+                Line::Text("```".into()),
+                Line::Text("two\nlines".into()),
+                Line::PreFormatted("a\n```\nb\n".into(), None),
+            ],
+        );
+        let serialized = doc.to_gemtext();
+        assert!(serialized.starts_with("\u{200B}# not a heading\n"));
+        assert!(serialized.contains("two lines\n"));
+
+        let reparsed = Document::parse(&url, &serialized);
+        let mut expected = doc.lines.clone();
+        expected[5] = Line::Text("two lines".into());
+        assert_eq!(reparsed.lines, expected);
+        Ok(())
+    }
+
+    // regression: the zero-width spaces were kept when parsing, so every
+    // save and load added another one
+    #[test]
+    fn test_to_gemtext_round_trip() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/")?;
+        let doc = Document::from_lines(
+            &url,
+            [
+                Line::Text("# not a heading".into()),
+                Line::Text("```".into()),
+                Line::Text("\u{200B}plain".into()),
+                Line::Text("\u{200B}> already escaped".into()),
+                Line::PreFormatted("```\n\u{200B}```\n\u{200B}x\n".into(), Some("alt".into())),
+            ],
+        );
+        let serialized = doc.to_gemtext();
+        let reparsed = Document::parse(&url, &serialized);
+        assert_eq!(reparsed, doc);
+        assert_eq!(reparsed.to_gemtext(), serialized);
+        // one for each line that would change type, and the four the
+        // document started with
+        assert_eq!(serialized.matches(ESCAPE).count(), 9);
+        Ok(())
+    }
+
+    #[test]
+    fn test_converters_round_trip() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/")?;
+        let docs = [
+            markdown::to_document(
+                &url,
+                "\\# one\n\n\\* two\n\n\\> three\n\n`=> four`\n\n````\n```\n````\n",
+            ),
+            html::to_document(
+                &url,
+                "<p># one</p><p>=&gt; two</p><p>* three</p><pre>a\n```\nb</pre>",
+            ),
+        ];
+        for doc in docs {
+            let serialized = doc.to_gemtext();
+            let reparsed = Document::parse(&url, &serialized);
+            assert_eq!(reparsed.to_gemtext(), serialized);
+            assert_eq!(reparsed.lines, doc.lines);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_large_document() {
+        // guards against accidentally quadratic parsing, e.g. re-scanning the
+        // rest of the document for every line, which would make this test
+        // take minutes instead of seconds
+        let url = Url::parse("gemini://example.com/").unwrap();
+        let mut doc = "=> /link text\n* item\n> quote\nplain text\n".repeat(50_000);
+        doc.push_str("```\n");
+        doc.push_str(&"x".repeat(1 << 20));
+        doc.push_str(&"\npreformatted".repeat(50_000));
+
+        let parsed = Document::parse(&url, &doc);
+        assert_eq!(parsed.lines.len(), 200_001);
+    }
 }
@@ -0,0 +1,339 @@
+//! HTML, reduced to gemtext lines: the text, headings, lists, quotes and
+//! preformatted blocks of a page, with its links listed after each block.
+
+use html5ever::{Attribute, ParseOpts, parse_document, tendril::TendrilSink};
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use url::Url;
+
+use crate::gemini::{gemtext::Document, line_writer::LineWriter};
+
+/// Elements nested deeper than this are left out, rather than risk running
+/// out of stack on a pathological page.
+const MAX_DEPTH: usize = 256;
+
+/// Converts `html` into a document. Scripts, styles, forms and anything
+/// hidden are dropped, and elements without a gemtext equivalent are reduced
+/// to their text, so any layout degrades to a column of lines. Relative
+/// links are resolved against the page's `<base>`, or else `url`.
+pub fn to_document(url: &Url, html: &str) -> Document {
+    let dom = parse_document(RcDom::default(), ParseOpts::default()).one(html);
+
+    let mut writer = LineWriter::new(url);
+    if let Some(base) = find(&dom.document, "base", 0)
+        .and_then(|x| attribute(&x, "href"))
+        .and_then(|x| url.join(&x).ok())
+    {
+        writer.set_base(base);
+    }
+    // the title stands in for a missing top-level heading
+    if find(&dom.document, "h1", 0).is_none()
+        && let Some(title) = find(&dom.document, "title", 0)
+    {
+        writer.start_heading(1);
+        writer.words(&text_content(&title, 0));
+        writer.end_heading();
+    }
+
+    let mut converter = Converter { writer };
+    converter.node(&dom.document, 0);
+    converter.writer.finish()
+}
+
+struct Converter {
+    writer: LineWriter,
+}
+
+impl Converter {
+    fn node(&mut self, node: &Handle, depth: usize) {
+        if depth > MAX_DEPTH {
+            log::warn!("html: leaving out elements nested past {MAX_DEPTH}");
+            return;
+        }
+        match &node.data {
+            NodeData::Document => self.children(node, depth),
+            NodeData::Text { contents } => self.writer.words(&contents.borrow()),
+            NodeData::Element { name, attrs, .. } => {
+                let attrs = attrs.borrow();
+                if !is_hidden(&attrs) {
+                    self.element(&name.local, &attrs, node, depth);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn children(&mut self, node: &Handle, depth: usize) {
+        for child in node.children.borrow().iter() {
+            self.node(child, depth + 1);
+        }
+    }
+
+    fn element(&mut self, tag: &str, attrs: &[Attribute], node: &Handle, depth: usize) {
+        let get = |name| {
+            attrs
+                .iter()
+                .find(|x| &*x.name.local == name)
+                .map(|x| &*x.value)
+        };
+        match tag {
+            "head" | "script" | "style" | "noscript" | "template" | "svg" | "math" | "canvas"
+            | "iframe" | "object" | "embed" | "button" | "select" | "textarea" | "input" => {}
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.writer
+                    .start_heading(usize::from(tag.as_bytes()[1] - b'0'));
+                self.children(node, depth);
+                self.writer.end_heading();
+            }
+            "p" | "table" => {
+                self.writer.flush();
+                self.children(node, depth);
+                self.writer.end_paragraph();
+            }
+            "tr" => {
+                self.writer.flush();
+                // cells are separated with bars, one row per line
+                let cells = node.children.borrow();
+                let cells = cells
+                    .iter()
+                    .filter(|x| matches!(x.data, NodeData::Element { .. }));
+                for (i, cell) in cells.enumerate() {
+                    if i > 0 {
+                        self.writer.text(" | ");
+                    }
+                    self.node(cell, depth + 1);
+                }
+                self.writer.flush();
+            }
+            "br" => self.writer.flush(),
+            "hr" => self.writer.rule(),
+            "ul" | "ol" | "menu" => {
+                let first = (tag == "ol").then(|| {
+                    get("start")
+                        .and_then(|x| x.trim().parse().ok())
+                        .unwrap_or(1)
+                });
+                self.writer.start_list(first);
+                self.children(node, depth);
+                self.writer.end_list();
+            }
+            "li" => {
+                self.writer.start_item();
+                self.children(node, depth);
+                self.writer.end_item();
+            }
+            "blockquote" => {
+                self.writer.start_quote();
+                self.children(node, depth);
+                self.writer.end_quote();
+            }
+            "pre" => {
+                let language = find(node, "code", depth)
+                    .and_then(|x| attribute(&x, "class"))
+                    .and_then(|x| {
+                        x.split_whitespace()
+                            .find_map(|x| x.strip_prefix("language-").map(str::to_string))
+                    });
+                self.writer
+                    .preformatted(text_content(node, depth), language);
+            }
+            "a" => match get("href").filter(|x| is_followable(x)) {
+                Some(href) => {
+                    self.writer.start_link(href);
+                    self.children(node, depth);
+                    self.writer.end_link();
+                }
+                None => self.children(node, depth),
+            },
+            "img" => {
+                let alt = get("alt").unwrap_or_default();
+                match get("src") {
+                    // the link it's in is labelled with it instead
+                    _ if self.writer.in_link() => self.writer.words(alt),
+                    Some(src) if is_followable(src) => {
+                        self.writer.start_link(src);
+                        self.writer.words(alt);
+                        self.writer.end_image();
+                    }
+                    _ => {}
+                }
+            }
+            "div" | "section" | "article" | "main" | "header" | "footer" | "nav" | "aside"
+            | "figure" | "figcaption" | "form" | "fieldset" | "address" | "details" | "summary"
+            | "dl" | "dt" | "dd" | "caption" | "center" => {
+                self.writer.flush();
+                self.children(node, depth);
+                self.writer.flush();
+            }
+            _ => self.children(node, depth),
+        }
+    }
+}
+
+fn is_hidden(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|x| {
+        let name = &*x.name.local;
+        name == "hidden" || (name == "aria-hidden" && &*x.value == "true")
+    })
+}
+
+/// Whether a link leads anywhere outside the page: scripts and links to
+/// fragments of the same page don't.
+fn is_followable(href: &str) -> bool {
+    let href = href.trim();
+    !href.is_empty()
+        && !href.starts_with('#')
+        && !href
+            .get(..11)
+            .is_some_and(|x| x.eq_ignore_ascii_case("javascript:"))
+}
+
+fn attribute(node: &Handle, name: &str) -> Option<String> {
+    let NodeData::Element { attrs, .. } = &node.data else {
+        return None;
+    };
+    let attrs = attrs.borrow();
+    attrs
+        .iter()
+        .find(|x| &*x.name.local == name)
+        .map(|x| x.value.to_string())
+}
+
+/// The first element named `tag` in `node`, depth first.
+fn find(node: &Handle, tag: &str, depth: usize) -> Option<Handle> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+    for child in node.children.borrow().iter() {
+        if let NodeData::Element { name, .. } = &child.data
+            && &*name.local == tag
+        {
+            return Some(child.clone());
+        }
+        if let Some(x) = find(child, tag, depth + 1) {
+            return Some(x);
+        }
+    }
+    None
+}
+
+/// All the text in `node`, as it's written.
+fn text_content(node: &Handle, depth: usize) -> String {
+    let mut out = String::new();
+    if depth > MAX_DEPTH {
+        return out;
+    }
+    for child in node.children.borrow().iter() {
+        match &child.data {
+            NodeData::Text { contents } => out.push_str(&contents.borrow()),
+            NodeData::Element { .. } => out.push_str(&text_content(child, depth + 1)),
+            _ => {}
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gemini::gemtext::Line;
+
+    fn text(s: &str) -> Line {
+        Line::Text(s.into())
+    }
+
+    #[test]
+    fn test_to_document() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://proxy.example/?https://example.org/blog/post.html")?;
+        let html = r##"<!DOCTYPE html>
+<html>
+<head>
+  <title>Ignored, as there's a heading</title>
+  <base href="https://example.org/blog/">
+  <style>body { color: red }</style>
+  <script>document.write("<p>nope</p>")</script>
+</head>
+<body>
+  <nav><a href="/">Home</a> <a href="#main">Skip</a></nav>
+  <h1>A   post</h1>
+  <p>Some <em>emphasised</em>
+     text, and a <a href="other.html">link</a>.
+  <p hidden>Hidden</p>
+  <h4>Small heading</h4>
+  <ul>
+    <li>one
+    <li>two<ol start="3"><li>three</ol>
+  </ul>
+  <blockquote><p>quoted</p></blockquote>
+  <pre><code class="language-rust">fn main() {
+    println!("hi");
+}</code></pre>
+  <table><tr><th>a</th><th>b</th></tr><tr><td>1</td><td>2</td></tr></table>
+  <p><a href="javascript:void(0)">Click</a> <a href="big.png"><img src="small.png" alt="A cat"></a>
+  <img src="/dog.png" alt="A dog"><br>after</p>
+</body>
+</html>"##;
+        let doc = to_document(&url, html);
+        assert_eq!(
+            doc.lines,
+            [
+                text("Home Skip"),
+                Line::Link(Url::parse("https://example.org/")?, Some("Home".into())),
+                Line::Heading(1, "A post".into()),
+                text(""),
+                text("Some emphasised text, and a link."),
+                Line::Link(
+                    Url::parse("https://example.org/blog/other.html")?,
+                    Some("link".into())
+                ),
+                text(""),
+                Line::Heading(3, "Small heading".into()),
+                text(""),
+                Line::List("one".into()),
+                Line::List("two".into()),
+                text("3. three"),
+                text(""),
+                Line::Quote("quoted".into()),
+                text(""),
+                Line::PreFormatted(
+                    "fn main() {\n    println!(\"hi\");\n}\n".into(),
+                    Some("rust".into())
+                ),
+                text(""),
+                text("a | b"),
+                text("1 | 2"),
+                text(""),
+                text("Click A cat"),
+                Line::Link(
+                    Url::parse("https://example.org/blog/big.png")?,
+                    Some("A cat".into())
+                ),
+                Line::Link(
+                    Url::parse("https://example.org/dog.png")?,
+                    Some("A dog".into())
+                ),
+                text("after"),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_title() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/index.html")?;
+        let doc = to_document(&url, "<title>Home</title><div><div>Hello</div></div>");
+        assert_eq!(
+            doc.lines,
+            [Line::Heading(1, "Home".into()), text(""), text("Hello")]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_deeply_nested() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/")?;
+        let html = format!("<p>top</p>{}deep", "<span>".repeat(10_000));
+        let doc = to_document(&url, &html);
+        assert_eq!(doc.lines, [text("top")]);
+        Ok(())
+    }
+}
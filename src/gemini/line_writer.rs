@@ -0,0 +1,233 @@
+//! Builds gemtext lines out of the blocks of richer formats, for the
+//! Markdown and HTML converters.
+
+use url::Url;
+
+use crate::gemini::gemtext::{Document, Line};
+
+/// A list item's marker.
+enum Item {
+    Bullet,
+    Numbered(u64),
+}
+
+/// Collects the text of each block, and writes it as a gemtext line of the
+/// kind of block it's in when the block ends. Gemtext has no inline markup,
+/// so links are written as link lines after the block they're in.
+pub(crate) struct LineWriter {
+    url: Url,
+    base: Url, // relative links are resolved against it
+    lines: Vec<Line>,
+    text: String,     // the inline text of the current block
+    links: Vec<Line>, // the links in the current block
+    // the destination of each open link or image, and where its label starts in `text`
+    open_links: Vec<(String, usize)>,
+    heading: Option<usize>,
+    lists: Vec<Option<u64>>, // the next number in each open list, if it's ordered
+    item: Option<Item>,      // an item whose text hasn't been written yet
+    quotes: usize,
+}
+
+impl LineWriter {
+    pub fn new(url: &Url) -> Self {
+        Self {
+            url: url.clone(),
+            base: url.clone(),
+            lines: Vec::new(),
+            text: String::new(),
+            links: Vec::new(),
+            open_links: Vec::new(),
+            heading: None,
+            lists: Vec::new(),
+            item: None,
+            quotes: 0,
+        }
+    }
+
+    pub fn finish(mut self) -> Document {
+        self.flush();
+        if self.lines.last() == Some(&Line::Text(String::new())) {
+            self.lines.pop();
+        }
+        Document::from_lines(&self.url, self.lines)
+    }
+
+    /// Resolves relative links against `base` instead of the document's URL.
+    pub fn set_base(&mut self, base: Url) {
+        self.base = base;
+    }
+
+    pub fn text(&mut self, s: &str) {
+        self.text.push_str(s);
+    }
+
+    /// Adds `s` with runs of whitespace collapsed into a single space, as
+    /// HTML is displayed.
+    pub fn words(&mut self, s: &str) {
+        let mut words = s.split_whitespace();
+        let Some(first) = words.next() else {
+            if !s.is_empty() && !self.text.ends_with(' ') {
+                self.text.push(' ');
+            }
+            return;
+        };
+        if s.starts_with(char::is_whitespace) && !self.text.ends_with(' ') {
+            self.text.push(' ');
+        }
+        self.text.push_str(first);
+        for word in words {
+            self.text.push(' ');
+            self.text.push_str(word);
+        }
+        if s.ends_with(char::is_whitespace) {
+            self.text.push(' ');
+        }
+    }
+
+    /// Writes the current block's text as a line of the kind it's in,
+    /// followed by its links. Later text in the same block starts a new line.
+    pub fn flush(&mut self) {
+        let text = std::mem::take(&mut self.text);
+        let text = text.trim();
+        if !text.is_empty() {
+            let line = if let Some(level) = self.heading {
+                Line::Heading(level, text.to_string())
+            } else if let Some(item) = self.item.take() {
+                match item {
+                    Item::Bullet => Line::List(text.to_string()),
+                    Item::Numbered(number) => Line::Text(format!("{number}. {text}")),
+                }
+            } else if self.quotes > 0 {
+                Line::Quote(text.to_string())
+            } else {
+                Line::Text(text.to_string())
+            };
+            self.lines.push(line);
+        }
+        self.lines.append(&mut self.links);
+        // a link across lines is labelled with the part on the last one
+        for (_, start) in &mut self.open_links {
+            *start = 0;
+        }
+    }
+
+    /// Ends a paragraph, or any other block separated from the next by a
+    /// blank line.
+    pub fn end_paragraph(&mut self) {
+        self.flush();
+        self.end_block();
+    }
+
+    /// Separates top-level blocks with a blank line, as gemtext authors do.
+    fn end_block(&mut self) {
+        let nested = !self.lists.is_empty() || self.quotes > 0;
+        let blank = Line::Text(String::new());
+        if !nested && self.lines.last().is_some_and(|x| *x != blank) {
+            self.lines.push(blank);
+        }
+    }
+
+    pub fn rule(&mut self) {
+        self.flush();
+        self.lines.push(Line::Text("───".into()));
+        self.end_block();
+    }
+
+    /// Each line of `text` ends with a newline, as when parsed from gemtext.
+    pub fn preformatted(&mut self, mut text: String, alt: Option<String>) {
+        self.flush();
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        self.lines.push(Line::PreFormatted(text, alt));
+        self.end_block();
+    }
+
+    /// Levels past 3 are written as level 3, gemtext's smallest heading.
+    pub fn start_heading(&mut self, level: usize) {
+        self.flush();
+        self.heading = Some(level.clamp(1, 3));
+    }
+
+    pub fn end_heading(&mut self) {
+        self.flush();
+        self.heading = None;
+        self.end_block();
+    }
+
+    pub fn start_quote(&mut self) {
+        self.flush();
+        self.quotes += 1;
+    }
+
+    pub fn end_quote(&mut self) {
+        self.flush();
+        self.quotes = self.quotes.saturating_sub(1);
+        self.end_block();
+    }
+
+    /// `first` is the number of an ordered list's first item.
+    pub fn start_list(&mut self, first: Option<u64>) {
+        // writes the text of the item this list is nested in
+        self.flush();
+        self.lists.push(first);
+    }
+
+    pub fn end_list(&mut self) {
+        self.flush();
+        self.lists.pop();
+        self.end_block();
+    }
+
+    pub fn start_item(&mut self) {
+        self.flush();
+        self.item = Some(match self.lists.last_mut() {
+            Some(Some(next)) => {
+                *next += 1;
+                Item::Numbered(*next - 1)
+            }
+            _ => Item::Bullet,
+        });
+    }
+
+    pub fn end_item(&mut self) {
+        self.flush();
+        self.item = None;
+    }
+
+    pub fn in_link(&self) -> bool {
+        !self.open_links.is_empty()
+    }
+
+    /// Starts a link or image. Its label is the text added until it ends.
+    pub fn start_link(&mut self, destination: &str) {
+        self.open_links
+            .push((destination.to_string(), self.text.len()));
+    }
+
+    pub fn end_link(&mut self) {
+        self.end_link_or_image(false);
+    }
+
+    /// An image's alt text is only used as its link's label, as the image
+    /// itself isn't shown inline.
+    pub fn end_image(&mut self) {
+        self.end_link_or_image(true);
+    }
+
+    fn end_link_or_image(&mut self, image: bool) {
+        let Some((destination, start)) = self.open_links.pop() else {
+            return;
+        };
+        let label = self.text[start..].trim().to_string();
+        if image {
+            self.text.truncate(start);
+        }
+        match self.base.join(&destination) {
+            Ok(url) => self
+                .links
+                .push(Line::Link(url, Some(label).filter(|x| !x.is_empty()))),
+            Err(e) => log::warn!("LineWriter: skipping link to {destination:?}: {e}"),
+        }
+    }
+}
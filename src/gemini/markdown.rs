@@ -0,0 +1,181 @@
+//! Markdown, converted into gemtext lines so it renders like any other page.
+
+use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag, TagEnd};
+use url::Url;
+
+use crate::gemini::{gemtext::Document, line_writer::LineWriter};
+
+/// Converts `markdown` into a document. Gemtext has no inline markup, so
+/// emphasis is dropped, and links are listed as link lines after the block
+/// they're in. Relative links are resolved against `url`.
+pub fn to_document(url: &Url, markdown: &str) -> Document {
+    let mut writer = LineWriter::new(url);
+    let mut code: Option<(String, Option<String>)> = None; // a code block, and its language
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(x) => {
+                        Some(x.trim().to_string()).filter(|x| !x.is_empty())
+                    }
+                    CodeBlockKind::Indented => None,
+                };
+                code = Some((String::new(), language));
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                if let Some((text, language)) = code.take() {
+                    writer.preformatted(text, language);
+                }
+            }
+            Event::Text(s) | Event::Code(s) => match &mut code {
+                Some((text, _)) => text.push_str(&s),
+                None => writer.text(&s),
+            },
+            Event::Start(tag) => start(&mut writer, tag),
+            Event::End(tag) => end(&mut writer, tag),
+            Event::SoftBreak => writer.text(" "),
+            Event::HardBreak => writer.flush(),
+            Event::Rule => writer.rule(),
+            // HTML, footnotes and task lists have no gemtext equivalent
+            _ => {}
+        }
+    }
+    writer.finish()
+}
+
+fn start(writer: &mut LineWriter, tag: Tag) {
+    match tag {
+        Tag::Heading { level, .. } => writer.start_heading(level as usize),
+        Tag::BlockQuote(_) => writer.start_quote(),
+        Tag::List(first) => writer.start_list(first),
+        Tag::Item => writer.start_item(),
+        Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. } => writer.start_link(&dest_url),
+        _ => {}
+    }
+}
+
+fn end(writer: &mut LineWriter, tag: TagEnd) {
+    match tag {
+        TagEnd::Paragraph => writer.end_paragraph(),
+        TagEnd::Heading(_) => writer.end_heading(),
+        TagEnd::BlockQuote(_) => writer.end_quote(),
+        TagEnd::List(_) => writer.end_list(),
+        TagEnd::Item => writer.end_item(),
+        TagEnd::Link => writer.end_link(),
+        TagEnd::Image => writer.end_image(),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gemini::gemtext::Line;
+
+    fn text(s: &str) -> Line {
+        Line::Text(s.into())
+    }
+
+    #[test]
+    fn test_to_document() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/docs/readme.md")?;
+        let markdown = "\
+# Title
+
+Some *emphasis* and a [link](other.md), with `code`.
+More of the same paragraph.
+
+#### Deep heading
+
+- one
+- two, see <https://example.org/>
+  1. nested
+  2. list
+
+> quoted
+> text
+
+```rust
+fn main() {}
+```
+
+    indented
+
+![a picture](/img/cat.png)
+
+---
+";
+        let doc = to_document(&url, markdown);
+        assert_eq!(
+            doc.lines,
+            [
+                Line::Heading(1, "Title".into()),
+                text(""),
+                text("Some emphasis and a link, with code. More of the same paragraph."),
+                Line::Link(
+                    Url::parse("gemini://example.com/docs/other.md")?,
+                    Some("link".into())
+                ),
+                text(""),
+                Line::Heading(3, "Deep heading".into()),
+                text(""),
+                Line::List("one".into()),
+                Line::List("two, see https://example.org/".into()),
+                Line::Link(
+                    Url::parse("https://example.org/")?,
+                    Some("https://example.org/".into())
+                ),
+                text("1. nested"),
+                text("2. list"),
+                text(""),
+                Line::Quote("quoted text".into()),
+                text(""),
+                Line::PreFormatted("fn main() {}\n".into(), Some("rust".into())),
+                text(""),
+                Line::PreFormatted("indented\n".into(), None),
+                text(""),
+                Line::Link(
+                    Url::parse("gemini://example.com/img/cat.png")?,
+                    Some("a picture".into())
+                ),
+                text(""),
+                text("───"),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_loose_list() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/")?;
+        let doc = to_document(&url, "- first\n\n  more about it\n\n- second\n\nafter\n");
+        assert_eq!(
+            doc.lines,
+            [
+                Line::List("first".into()),
+                text("more about it"),
+                Line::List("second".into()),
+                text(""),
+                text("after"),
+            ]
+        );
+        Ok(())
+    }
+
+    // regression: a hard break cleared the text without moving the start of
+    // the open link's label, which then pointed past the end of it
+    #[test]
+    fn test_hard_break_in_link() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/")?;
+        let doc = to_document(&url, "Some long text [a  \nb](x)\n");
+        assert_eq!(
+            doc.lines,
+            [
+                text("Some long text a"),
+                text("b"),
+                Line::Link(Url::parse("gemini://example.com/x")?, Some("b".into())),
+            ]
+        );
+        Ok(())
+    }
+}
@@ -1,7 +1,17 @@
 use thiserror::Error;
 
+pub mod ansi;
 pub mod client;
+pub mod content;
+pub mod diff;
+pub mod dump;
+pub mod export;
+pub mod feed;
+pub mod find;
 pub mod gemtext;
+pub mod html;
+mod line_writer;
+pub mod markdown;
 pub mod response;
 
 #[derive(Error, Debug)]
@@ -67,3 +77,21 @@ impl TryFrom<u8> for Status {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn test_status_try_from(code in any::<u8>()) {
+            match Status::try_from(code) {
+                // unknown codes fall back to the generic code of their class
+                Ok(status) => prop_assert_eq!(status as u8 / 10, code / 10),
+                Err(_) => prop_assert!(!(10..70).contains(&code)),
+            }
+        }
+    }
+}
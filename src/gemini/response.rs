@@ -30,18 +30,88 @@ impl TryFrom<&[u8]> for Response {
         let body = spl
             .next()
             .ok_or(ResponseError::ParsingError("No body in response".into()))?;
-        let mut spl = status.splitn(2, ' ');
+        // `<two digit status>[ <meta>]`, tolerating a missing CR
+        let status = status.strip_suffix('\r').unwrap_or(&status);
+        let (code, meta) = status.split_once(' ').unwrap_or((status, ""));
 
-        let status = spl
-            .next()
+        let status = Some(code)
+            .filter(|s| s.len() == 2 && s.bytes().all(|x| x.is_ascii_digit()))
             .and_then(|s| s.parse::<u8>().ok())
             .and_then(|s| Status::try_from(s).ok())
             .ok_or_else(|| ResponseError::ParsingError("invalid status code".to_string()))?;
+        let meta = meta.trim();
 
         Ok(Response {
             status,
-            ctx: spl.next().map(|s| s.trim().to_string()),
+            ctx: Some(meta.to_string()).filter(|x| !x.is_empty()),
             body: Some(body.to_vec()),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_response() -> anyhow::Result<()> {
+        let r = Response::try_from(&b"20 text/gemini; lang=en\r\n# hello\n"[..])?;
+        assert_eq!(r.status, Status::Success);
+        assert_eq!(r.ctx.as_deref(), Some("text/gemini; lang=en"));
+        assert_eq!(r.body.as_deref(), Some(&b"# hello\n"[..]));
+
+        let r = Response::try_from(&b"31 gemini://example.com/\n"[..])?;
+        assert_eq!(r.status, Status::PermanentRedirect);
+        assert_eq!(r.ctx.as_deref(), Some("gemini://example.com/"));
+        Ok(())
+    }
+
+    // regressions: a header without meta used to be rejected, and `+20` was
+    // accepted as status 20 by `str::parse`
+    #[test]
+    fn test_parse_response_status() -> anyhow::Result<()> {
+        let r = Response::try_from(&b"51\r\n"[..])?;
+        assert_eq!(r.status, Status::NotFound);
+        assert_eq!(r.ctx, None);
+
+        let r = Response::try_from(&b"20 \r\n"[..])?;
+        assert_eq!(r.ctx, None);
+
+        for header in [
+            &b"+20 x\r\n"[..],
+            b"020 x\r\n",
+            b"2 x\r\n",
+            b"99 x\r\n",
+            b"\r\n",
+        ] {
+            assert!(Response::try_from(header).is_err());
+        }
+        assert!(Response::try_from(&b"20 text/gemini"[..]).is_err());
+        assert!(Response::try_from(&[0xff, b' ', b'\n'][..]).is_err());
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn test_parse_response_never_panics(bytes in any::<Vec<u8>>()) {
+            let _ = Response::try_from(&bytes[..]);
+        }
+
+        #[test]
+        fn test_parse_response_fields(
+            code in 10u8..70,
+            meta in "[^\r\n]{0,64}",
+            body in any::<Vec<u8>>(),
+        ) {
+            let mut raw = format!("{code} {meta}\r\n").into_bytes();
+            raw.extend_from_slice(&body);
+
+            let r = Response::try_from(&raw[..]).unwrap();
+            prop_assert_eq!(r.status as u8 / 10, code / 10);
+            prop_assert_eq!(r.ctx.unwrap_or_default(), meta.trim());
+            prop_assert_eq!(r.body.unwrap(), body);
+        }
+    }
+}
@@ -0,0 +1,382 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        OnceLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use url::Url;
+
+use iced_gemini::gemini::content::MimePattern;
+
+use crate::{
+    config::{self, LineError},
+    handlers::{shell_quote, spawn_shell_then},
+};
+
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+static TEMP_DIR: OnceLock<PathBuf> = OnceLock::new();
+/// How many names are tried for the temporary directory before giving up.
+const TEMP_DIR_ATTEMPTS: u32 = 16;
+const FORMAT: &str = "`<type>; <command>[; <flag>...]`";
+
+/// A single mailcap rule, e.g. `audio/*; mpv %s`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MailcapEntry {
+    pub mime: MimePattern,
+    pub command: String,
+    pub name_template: Option<String>,
+}
+
+/// An ordered list of mailcap rules. As with mailcap(5), the first matching rule wins.
+#[derive(Debug, Clone, Default)]
+pub struct Mailcap {
+    entries: Vec<MailcapEntry>,
+}
+
+impl Mailcap {
+    /// Loads the rules from `mailcap` in the config directory. A missing file
+    /// results in an empty rule set, and malformed entries are skipped.
+    pub fn load() -> Self {
+        let Some(path) = config::config_dir().map(|dir| dir.join("mailcap")) else {
+            return Self::default();
+        };
+
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(x) => x,
+            Err(e) => {
+                log::info!("Mailcap: not loading {}: {e}", path.display());
+                return Self::default();
+            }
+        };
+
+        let (mailcap, errors) = Self::parse(&contents);
+        for e in errors {
+            log::warn!("Mailcap: skipping in {}: {e}", path.display());
+        }
+        mailcap
+    }
+
+    /// Parses the rules, skipping and returning the errors for the entries
+    /// that can't be parsed so that the rest are still kept.
+    pub fn parse(s: &str) -> (Self, Vec<LineError>) {
+        let mut entries = Vec::new();
+        let mut errors = Vec::new();
+        let mut logical_line = String::new();
+        let mut start = 0;
+
+        for (i, line) in s.lines().enumerate() {
+            if logical_line.is_empty() {
+                start = i + 1;
+            }
+
+            // a trailing backslash continues the entry on the next line
+            if let Some(line) = line.strip_suffix('\\') {
+                logical_line.push_str(line);
+                continue;
+            }
+            logical_line.push_str(line);
+
+            let line = std::mem::take(&mut logical_line);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match MailcapEntry::parse(start, line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        (Self { entries }, errors)
+    }
+
+    pub fn find(&self, mime: &mime::Mime) -> Option<&MailcapEntry> {
+        self.entries.iter().find(|entry| entry.mime.matches(mime))
+    }
+}
+
+impl MailcapEntry {
+    fn parse(line_number: usize, line: &str) -> Result<Self, LineError> {
+        let malformed = || LineError::MalformedLine(line_number, FORMAT);
+        let mut fields = split_fields(line).into_iter();
+
+        let mime = fields
+            .next()
+            .and_then(|x| x.parse().ok())
+            .ok_or_else(malformed)?;
+
+        let command = fields
+            .next()
+            .filter(|x| !x.is_empty())
+            .ok_or_else(malformed)?;
+
+        // we don't act on any of the other flags (needsterminal, test=, ...)
+        let name_template = fields.find_map(|field| {
+            let (key, value) = field.split_once('=')?;
+            (key.trim() == "nametemplate").then(|| value.trim().to_string())
+        });
+
+        Ok(Self {
+            mime,
+            command,
+            name_template,
+        })
+    }
+
+    /// Builds the shell command for `path`. `%s` is replaced with the file name
+    /// and `%t` with the content type. The returned flag is `false` when the
+    /// command doesn't reference the file, in which case it's supplied on stdin.
+    fn command_line(&self, path: &str, mime: &mime::Mime) -> (String, bool) {
+        let mut command = String::with_capacity(self.command.len());
+        let mut uses_file = false;
+        let mut chars = self.command.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                command.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('s') => {
+                    uses_file = true;
+                    command.push_str(&shell_quote(path));
+                }
+                Some('t') => command.push_str(&shell_quote(mime.essence_str())),
+                Some(x) => command.push(x),
+                None => command.push('%'),
+            }
+        }
+        (command, uses_file)
+    }
+
+    /// The file name `body` is saved under for the viewer: the last segment
+    /// of `url`, applied to the name template if there is one.
+    fn temp_name(&self, url: &Url) -> String {
+        let name = url
+            .path_segments()
+            .and_then(|mut x| x.next_back())
+            .filter(|x| !x.is_empty())
+            .unwrap_or("download");
+        let name: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || "._-".contains(c) {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        match &self.name_template {
+            Some(template) => template.replace("%s", &name),
+            None => name,
+        }
+    }
+
+    /// Writes `body` to a temporary file and launches the viewer with it.
+    /// This doesn't wait for the viewer to exit; the file is removed once
+    /// it has.
+    pub fn launch(&self, url: &Url, mime: &mime::Mime, body: &[u8]) -> io::Result<()> {
+        let path = write_temp_file(temp_dir()?, &self.temp_name(url), body)?;
+
+        let (command, uses_file) = self.command_line(&path.to_string_lossy(), mime);
+        log::info!("Mailcap: launching `{command}` for {url}");
+
+        let stdin = if uses_file {
+            None
+        } else {
+            Some(File::open(&path)?)
+        };
+        let remove = path.clone();
+        let result = spawn_shell_then(command, stdin, move || {
+            if let Err(e) = std::fs::remove_file(&remove) {
+                log::warn!("Mailcap: failed to remove {}: {e}", remove.display());
+            }
+        });
+        if result.is_err() {
+            let _ = std::fs::remove_file(&path);
+        }
+        result
+    }
+}
+
+/// A directory for the files handed to viewers, only accessible to us and
+/// created afresh by this process. Creating it fails if anything is already
+/// at the path, so it can't be one planted by another user.
+fn temp_dir() -> io::Result<&'static Path> {
+    if let Some(dir) = TEMP_DIR.get() {
+        return Ok(dir);
+    }
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.subsec_nanos());
+    for i in 0..TEMP_DIR_ATTEMPTS {
+        let dir = std::env::temp_dir().join(format!(
+            "iced_gemini-{}-{nanos:08x}-{i}",
+            std::process::id()
+        ));
+        match create_private_dir(&dir) {
+            Ok(()) => return Ok(TEMP_DIR.get_or_init(|| dir)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        "no free name for a temporary directory",
+    ))
+}
+
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir)
+}
+
+/// Writes `data` to a new file named `name` in `dir`, prefixed with a
+/// number so launches of the same URL don't collide.
+fn write_temp_file(dir: &Path, name: &str, data: &[u8]) -> io::Result<PathBuf> {
+    let n = TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let path = dir.join(format!("{n}-{name}"));
+    // never follows a link or reuses a file that's already there
+    File::create_new(&path)?.write_all(data)?;
+    Ok(path)
+}
+
+/// Splits a mailcap entry on `;`, honouring `\;` escapes.
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(';') => field.push(';'),
+                Some(x) => {
+                    field.push('\\');
+                    field.push(x);
+                }
+                None => field.push('\\'),
+            },
+            ';' => fields.push(std::mem::take(&mut field).trim().to_string()),
+            x => field.push(x),
+        }
+    }
+    fields.push(field.trim().to_string());
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_entries() -> anyhow::Result<()> {
+        let (mailcap, errors) = Mailcap::parse(
+            "# comment\n\naudio/*; mpv %s\napplication/pdf; zathura %s; nametemplate=%s.pdf\n",
+        );
+        assert!(errors.is_empty());
+        assert_eq!(
+            mailcap.entries,
+            vec![
+                MailcapEntry {
+                    mime: "audio/*".parse()?,
+                    command: "mpv %s".into(),
+                    name_template: None,
+                },
+                MailcapEntry {
+                    mime: "application/pdf".parse()?,
+                    command: "zathura %s".into(),
+                    name_template: Some("%s.pdf".into()),
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_escapes_and_continuations() -> anyhow::Result<()> {
+        let (mailcap, errors) = Mailcap::parse("video; mpv %s \\; echo done;\\\n needsterminal\n");
+        assert!(errors.is_empty());
+        assert_eq!(mailcap.entries.len(), 1);
+        assert_eq!(mailcap.entries[0].mime, "video/*".parse()?);
+        assert_eq!(mailcap.entries[0].command, "mpv %s ; echo done");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_skips_malformed_entries() -> anyhow::Result<()> {
+        let (mailcap, errors) = Mailcap::parse("audio/*\n/ogg; mpv %s\nimage/*; display\n");
+        assert!(matches!(
+            errors[..],
+            [
+                LineError::MalformedLine(1, _),
+                LineError::MalformedLine(2, _),
+            ]
+        ));
+        // the entries after the bad ones are still kept
+        assert_eq!(mailcap.entries.len(), 1);
+        assert_eq!(mailcap.entries[0].mime, "image/*".parse()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_first_match() -> anyhow::Result<()> {
+        let (mailcap, _) = Mailcap::parse("audio/ogg; ogg123 %s\naudio/*; mpv %s\n");
+        let ogg: mime::Mime = "audio/ogg".parse()?;
+        let mp3: mime::Mime = "audio/mpeg".parse()?;
+        let pdf: mime::Mime = "application/pdf".parse()?;
+        assert_eq!(
+            mailcap.find(&ogg).map(|x| x.command.as_str()),
+            Some("ogg123 %s")
+        );
+        assert_eq!(
+            mailcap.find(&mp3).map(|x| x.command.as_str()),
+            Some("mpv %s")
+        );
+        assert!(mailcap.find(&pdf).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_temp_file() -> anyhow::Result<()> {
+        let dir = temp_dir()?;
+        assert_eq!(temp_dir()?, dir);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(dir)?.permissions().mode() & 0o777, 0o700);
+        }
+
+        let first = write_temp_file(dir, "page.pdf", b"one")?;
+        let second = write_temp_file(dir, "page.pdf", b"two")?;
+        assert_ne!(first, second);
+        assert_eq!(std::fs::read(&first)?, b"one");
+        std::fs::remove_file(first)?;
+        std::fs::remove_file(second)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_command_line() -> anyhow::Result<()> {
+        let (mailcap, _) = Mailcap::parse("audio/*; mpv --title=%t %s 100%%\nimage/*; display\n");
+        let ogg: mime::Mime = "audio/ogg".parse()?;
+        assert_eq!(
+            mailcap.entries[0].command_line("/tmp/it's.ogg", &ogg),
+            (
+                r"mpv --title='audio/ogg' '/tmp/it'\''s.ogg' 100%".to_string(),
+                true
+            )
+        );
+        assert_eq!(
+            mailcap.entries[1].command_line("/tmp/x.png", &ogg),
+            ("display".to_string(), false)
+        );
+        Ok(())
+    }
+}
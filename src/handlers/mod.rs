@@ -0,0 +1,117 @@
+use std::{
+    fs::File,
+    process::{Command, Stdio},
+};
+
+pub mod mailcap;
+pub mod scheme;
+
+/// Runs `command` through the platform shell without waiting for it to exit.
+/// If given, `stdin` is connected to the command's standard input.
+pub fn spawn_shell(command: String, stdin: Option<File>) -> std::io::Result<()> {
+    spawn_shell_then(command, stdin, || {})
+}
+
+/// Like [`spawn_shell`], calling `after` once the command has exited.
+pub fn spawn_shell_then(
+    command: String,
+    stdin: Option<File>,
+    after: impl FnOnce() + Send + 'static,
+) -> std::io::Result<()> {
+    #[cfg(windows)]
+    let mut cmd = {
+        use std::os::windows::process::CommandExt;
+        let mut cmd = Command::new("cmd");
+        // as is: quoting it again for the C runtime would undo `cmd_quote`
+        cmd.arg("/C").raw_arg(&command);
+        cmd
+    };
+    #[cfg(not(windows))]
+    let mut cmd = {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(&command);
+        cmd
+    };
+    if let Some(stdin) = stdin {
+        cmd.stdin(Stdio::from(stdin));
+    }
+
+    let mut child = cmd.spawn()?;
+    // reap the child so it doesn't linger as a zombie
+    std::thread::spawn(move || {
+        match child.wait() {
+            Ok(status) if !status.success() => {
+                log::error!("spawn_shell: `{command}` exited with {status}");
+            }
+            Err(e) => log::error!("spawn_shell: failed to wait on `{command}`: {e}"),
+            Ok(_) => {}
+        }
+        after();
+    });
+
+    Ok(())
+}
+
+/// Quotes `s` so the shell passes it to the command as a single argument.
+pub fn shell_quote(s: &str) -> String {
+    if cfg!(windows) {
+        cmd_quote(s)
+    } else {
+        format!("'{}'", s.replace('\'', r"'\''"))
+    }
+}
+
+/// Quotes `s` as a single argument for a program run by `cmd /C`: first as
+/// the C runtime splits arguments, then with every character `cmd` treats
+/// specially escaped with `^`, quotes included, so `cmd` never sees a quoted
+/// string it could be tricked into leaving early.
+fn cmd_quote(s: &str) -> String {
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in s.chars() {
+        match c {
+            '\\' => {
+                backslashes += 1;
+                continue;
+            }
+            // backslashes are only special before a quote
+            '"' => {
+                quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+                quoted.push('"');
+            }
+            c => {
+                quoted.push_str(&"\\".repeat(backslashes));
+                quoted.push(c);
+            }
+        }
+        backslashes = 0;
+    }
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+
+    let mut escaped = String::with_capacity(quoted.len());
+    for c in quoted.chars() {
+        if "()%!^\"<>&|".contains(c) {
+            escaped.push('^');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cmd_quote() {
+        assert_eq!(cmd_quote("plain"), r#"^"plain^""#);
+        assert_eq!(
+            cmd_quote("gemini://x/?a&b=%PATH%"),
+            r#"^"gemini://x/?a^&b=^%PATH^%^""#
+        );
+        // a quote can't end the argument early, for cmd or the program
+        assert_eq!(cmd_quote(r#"x" & calc & ""#), r#"^"x\^" ^& calc ^& \^"^""#);
+        assert_eq!(cmd_quote(r"a\b\"), r#"^"a\b\\^""#);
+    }
+}
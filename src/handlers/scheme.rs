@@ -0,0 +1,231 @@
+use std::{collections::HashMap, fmt};
+
+use thiserror::Error;
+use url::Url;
+
+use crate::{
+    config::{self, ConfigFile, LineError},
+    handlers::{shell_quote, spawn_shell},
+};
+
+const FORMAT: &str = "`<scheme> <native|system|proxy <url>|command <command>>`";
+
+#[derive(Error, Debug)]
+pub enum SchemeError {
+    #[error("no native fetcher for `{0}:` urls")]
+    NoNativeFetcher(String),
+}
+
+/// What to do when navigating to a URL of a given scheme.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemeHandler {
+    /// Fetch it with the built-in Gemini client.
+    Native,
+    /// Fetch it through a Gemini proxy, e.g. a Gemini-to-HTTP gateway.
+    Proxy(Url),
+    /// Run an external command, with `%s` replaced by the URL.
+    Command(String),
+    /// Hand it to the system's default application.
+    System,
+}
+
+impl SchemeHandler {
+    /// Whether following the link launches another application. These need
+    /// to be confirmed by the user first.
+    pub fn is_external(&self) -> bool {
+        matches!(self, SchemeHandler::Command(_) | SchemeHandler::System)
+    }
+
+    /// Launches an external handler. Does nothing for `Native` and `Proxy`.
+    pub fn launch(&self, url: &Url) -> anyhow::Result<()> {
+        match self {
+            SchemeHandler::Native | SchemeHandler::Proxy(_) => {}
+            SchemeHandler::Command(command) => {
+                let command = command.replace("%s", &shell_quote(url.as_str()));
+                log::info!("SchemeHandler: launching `{command}`");
+                spawn_shell(command, None)?;
+            }
+            SchemeHandler::System => opener::open(url.as_str())?,
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SchemeHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemeHandler::Native => write!(f, "the built-in client"),
+            SchemeHandler::Proxy(proxy) => write!(f, "the proxy at {proxy}"),
+            SchemeHandler::Command(command) => write!(f, "`{command}`"),
+            SchemeHandler::System => write!(f, "the system default application"),
+        }
+    }
+}
+
+/// Maps URL schemes to handlers. Anything without an entry falls back to
+/// the `*` entry.
+#[derive(Debug, Clone)]
+pub struct SchemeHandlers {
+    handlers: HashMap<String, SchemeHandler>,
+    fallback: SchemeHandler,
+}
+
+/// The `schemes` file overrides the built-in defaults, one `<scheme>
+/// <handler> [argument]` per line, e.g. `mailto command thunderbird
+/// -compose %s`.
+impl ConfigFile for SchemeHandlers {
+    const NAME: &'static str = "schemes";
+
+    type Error = LineError;
+
+    fn parse_line(&mut self, line_number: usize, line: &str) -> Result<(), LineError> {
+        let malformed = || LineError::MalformedLine(line_number, FORMAT);
+
+        let (scheme, rest) = line.split_once(char::is_whitespace).ok_or_else(malformed)?;
+        let rest = rest.trim_start();
+        let (handler, argument) = match rest.split_once(char::is_whitespace) {
+            Some((handler, argument)) => (handler, Some(argument.trim())),
+            None => (rest, None),
+        };
+
+        let handler = match (handler, argument) {
+            ("native", _) => SchemeHandler::Native,
+            ("system", _) => SchemeHandler::System,
+            ("proxy", Some(proxy)) => SchemeHandler::Proxy(config::parse_url(line_number, proxy)?),
+            ("command", Some(command)) => SchemeHandler::Command(command.to_string()),
+            _ => return Err(malformed()),
+        };
+
+        self.set(scheme, handler);
+        Ok(())
+    }
+}
+
+impl fmt::Display for SchemeHandlers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut schemes: Vec<_> = self
+            .handlers
+            .iter()
+            .map(|(scheme, handler)| (scheme.as_str(), handler))
+            .collect();
+        schemes.sort_by_key(|(scheme, _)| *scheme);
+        schemes.push(("*", &self.fallback));
+        for (scheme, handler) in schemes {
+            match handler {
+                SchemeHandler::Native => writeln!(f, "{scheme} native")?,
+                SchemeHandler::System => writeln!(f, "{scheme} system")?,
+                SchemeHandler::Proxy(proxy) => writeln!(f, "{scheme} proxy {proxy}")?,
+                SchemeHandler::Command(command) => writeln!(f, "{scheme} command {command}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SchemeHandlers {
+    pub fn set(&mut self, scheme: &str, handler: SchemeHandler) {
+        if scheme == "*" {
+            self.fallback = handler;
+        } else {
+            self.handlers.insert(scheme.to_ascii_lowercase(), handler);
+        }
+    }
+
+    pub fn get(&self, scheme: &str) -> &SchemeHandler {
+        self.handlers.get(scheme).unwrap_or(&self.fallback)
+    }
+
+    /// Looks up the handler for `url`, checking that it can be honoured.
+    pub fn resolve(&self, url: &Url) -> Result<&SchemeHandler, SchemeError> {
+        let handler = self.get(url.scheme());
+        if *handler == SchemeHandler::Native && url.scheme() != "gemini" {
+            return Err(SchemeError::NoNativeFetcher(url.scheme().to_string()));
+        }
+        Ok(handler)
+    }
+}
+
+impl Default for SchemeHandlers {
+    fn default() -> Self {
+        Self {
+            handlers: HashMap::from([("gemini".to_string(), SchemeHandler::Native)]),
+            fallback: SchemeHandler::System,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults() -> anyhow::Result<()> {
+        let handlers = SchemeHandlers::default();
+        let gemini = Url::parse("gemini://example.com/")?;
+        let mailto = Url::parse("mailto:someone@example.com")?;
+        assert_eq!(handlers.resolve(&gemini)?, &SchemeHandler::Native);
+        assert_eq!(handlers.resolve(&mailto)?, &SchemeHandler::System);
+        assert!(handlers.resolve(&mailto)?.is_external());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let (handlers, errors) = SchemeHandlers::parse(
+            "# comment\n\
+             http   proxy gemini://proxy.example.com/\n\
+             mailto command thunderbird -compose %s\n\
+             *      native\n",
+        );
+        assert!(errors.is_empty());
+        assert_eq!(
+            handlers.get("http"),
+            &SchemeHandler::Proxy(Url::parse("gemini://proxy.example.com/")?)
+        );
+        assert_eq!(
+            handlers.get("mailto"),
+            &SchemeHandler::Command("thunderbird -compose %s".into())
+        );
+        assert_eq!(handlers.get("gemini"), &SchemeHandler::Native);
+        assert_eq!(handlers.get("finger"), &SchemeHandler::Native);
+        assert!(matches!(
+            handlers.resolve(&Url::parse("finger://example.com/")?),
+            Err(SchemeError::NoNativeFetcher(_))
+        ));
+
+        let (parsed, errors) = SchemeHandlers::parse(&handlers.to_string());
+        assert!(errors.is_empty());
+        assert_eq!(parsed.handlers, handlers.handlers);
+        assert_eq!(parsed.fallback, handlers.fallback);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_skips_bad_lines() -> anyhow::Result<()> {
+        let (handlers, errors) = SchemeHandlers::parse(
+            "mailto system\n\
+             http\n\
+             gopher proxy\n\
+             finger open\n\
+             spartan proxy not a url\n\
+             http proxy gemini://proxy.example.com/\n",
+        );
+        assert!(matches!(
+            errors[..],
+            [
+                LineError::MalformedLine(2, _),
+                LineError::MalformedLine(3, _),
+                LineError::MalformedLine(4, _),
+                LineError::InvalidUrl(5, _),
+            ]
+        ));
+        // the lines around the bad ones still apply
+        assert_eq!(handlers.get("mailto"), &SchemeHandler::System);
+        assert_eq!(
+            handlers.get("http"),
+            &SchemeHandler::Proxy(Url::parse("gemini://proxy.example.com/")?)
+        );
+        assert_eq!(handlers.get("gemini"), &SchemeHandler::Native);
+        Ok(())
+    }
+}
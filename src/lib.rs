@@ -0,0 +1,8 @@
+//! The UI-agnostic core of the client: the Gemini protocol, TLS with
+//! trust-on-first-use, and gemtext parsing.
+
+// errors are self-describing enums, and most getters are obviously must-use
+#[warn(clippy::all, clippy::pedantic)]
+#[allow(clippy::missing_errors_doc, clippy::must_use_candidate)]
+pub mod gemini;
+pub mod net;
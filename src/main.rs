@@ -1,21 +1,57 @@
 #[warn(clippy::all, clippy::pedantic)]
-mod gemini;
-mod net;
+mod cli;
+mod config;
+mod export;
+mod feeds;
+mod handlers;
+mod settings;
 mod ui;
+mod watches;
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 use iced::Length::Fill;
+use iced::advanced::widget::Id;
+use iced::futures::channel::oneshot;
+use iced::time::Instant;
 use iced::widget::scrollable::AbsoluteOffset;
-use iced::widget::{Row, button, column, container, scrollable, text, text_input};
-use iced::{Element, Font, Subscription, Task, application};
+use iced::widget::{Row, button, column, container, operation, row, scrollable, text, text_input};
+use iced::{Element, Font, Subscription, Task, application, keyboard, task, window};
+use iced_gemini::gemini::Status;
+use iced_gemini::gemini::ansi;
+use iced_gemini::gemini::client::{Client, Exchange, FetchOutcome};
+use iced_gemini::gemini::content::guess_mime;
+use iced_gemini::gemini::diff::Change;
+use iced_gemini::gemini::dump::TextRenderer;
+use iced_gemini::gemini::feed::Feed;
+use iced_gemini::gemini::find::{self, Match, Query};
+use iced_gemini::gemini::gemtext::{Document, Line};
 use url::Url;
 
-use crate::gemini::client::Client;
-use crate::gemini::gemtext::Document;
-use crate::gemini::response::Response;
+use crate::config::ConfigFile;
+use crate::export::Format;
+use crate::feeds::Feeds;
+use crate::handlers::mailcap::Mailcap;
+use crate::handlers::scheme::{SchemeHandler, SchemeHandlers};
+use crate::settings::Settings;
+use crate::ui::confirm_modal::ConfirmModal;
+use crate::ui::document_view::{self, DocumentView};
 use crate::ui::error_dialog::ErrorDialog;
+use crate::ui::export_modal::ExportModal;
+use crate::ui::find_bar::{FIND_INPUT, FindBar};
 use crate::ui::gemini_text::GeminiText;
+use crate::ui::highlight::{HighlightedBlock, Highlighter};
+use crate::ui::image_cache::ImageCache;
+use crate::ui::image_view::{self, ImageFit, ImageInfo, ImageView};
 use crate::ui::input_modal::InputRequest;
 use crate::ui::modal::Modal;
+use crate::ui::outline::{self, Outline};
+use crate::ui::picture::{Animation, Picture};
+use crate::ui::settings_modal::SettingsModal;
+use crate::watches::Watches;
 
 pub fn main() -> iced::Result {
     env_logger::init();
@@ -23,6 +59,11 @@ pub fn main() -> iced::Result {
         .install_default()
         .expect("Failed to install default crypto provider");
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
+
     let app = application(
         || {
             let t = Task::done(Message::PageLoad(
@@ -33,14 +74,29 @@ pub fn main() -> iced::Result {
         GeminiClient::update,
         GeminiClient::view,
     )
+    .subscription(GeminiClient::subscription)
     .default_font(Font::with_name("Arial"));
     app.run()
 }
 
+/// The most image previews fetched for one page, one after another.
+const MAX_PREVIEWS: usize = 32;
+/// How often subscribed feeds are refreshed and watched pages checked for
+/// changes, checked whenever a page loads.
+const FEEDS_REFRESH_INTERVAL: Duration = Duration::from_secs(30 * 60);
+const FEEDS_URL: &str = "about:feeds";
+/// The document's container in the scrollable, which lines are found
+/// relative to.
+const CONTENT_ID: Id = Id::new("document");
+/// How far above the line of a match found it's scrolled to.
+const MATCH_MARGIN: f32 = 100.0;
+
 struct GeminiClient {
     uri: String,
     document: Option<Document>,
     client: Client,
+    mailcap: Arc<Mailcap>,
+    images: ImageCache, // of the current page
     scroll_id: scrollable::Id,
     scroll_position: AbsoluteOffset,
     history_back: Vec<(Document, AbsoluteOffset)>,
@@ -48,6 +104,39 @@ struct GeminiClient {
     errors: Vec<String>,
     input_text: String,
     input_request: Option<InputRequest>,
+    schemes: SchemeHandlers,
+    pending_launch: Option<(Url, SchemeHandler)>,
+    settings: Settings,
+    show_settings: bool,
+    show_export: bool,
+    exporting: bool,
+    export_status: Option<String>, // where the last export was saved
+    expanded_preformatted: HashSet<usize>, // line indices
+    highlighter: Arc<Highlighter>,
+    highlighted: HashMap<usize, HighlightedBlock>, // by line index
+    highlighting: u64,                             // the latest highlighting started
+    previews: Option<task::Handle>,                // aborts the previews being fetched when dropped
+    image_fit: ImageFit,
+    image_rotation: u8,             // quarter turns clockwise
+    rotated_image: Option<Picture>, // the image response, turned by image_rotation
+    image_info: Option<ImageInfo>,  // of the image response
+    image_status: Option<String>,
+    animations_paused: bool,
+    feeds: Feeds,
+    feeds_fetching: HashSet<Url>, // feeds and watched pages being fetched
+    feeds_queued: Vec<Url>,       // waiting for a fetch from their host to finish
+    feeds_refreshed_at: Option<Instant>,
+    feed_failures: usize, // in the last refresh
+    watches: Watches,
+    // the current page with the lines removed since it was last seen put back
+    page_changes: Option<(Document, Vec<Change>)>,
+    show_outline: bool,
+    heading_offsets: Vec<(usize, f32)>, // line index, offset in the scrollable
+    show_find: bool,
+    find_query: Query,
+    found: Vec<Match>, // in the shown document
+    current_match: Option<usize>,
+    find_error: Option<String>, // why the query couldn't be searched for
 }
 
 impl Default for GeminiClient {
@@ -56,6 +145,8 @@ impl Default for GeminiClient {
             uri: Default::default(),
             document: Default::default(),
             client: Default::default(),
+            mailcap: Arc::new(Mailcap::load()),
+            images: Default::default(),
             scroll_id: scrollable::Id::unique(),
             history_back: Default::default(),
             history_forward: Default::default(),
@@ -63,6 +154,38 @@ impl Default for GeminiClient {
             errors: Default::default(),
             input_text: Default::default(),
             input_request: Default::default(),
+            schemes: SchemeHandlers::load(),
+            pending_launch: Default::default(),
+            settings: Settings::load(),
+            show_settings: Default::default(),
+            show_export: Default::default(),
+            exporting: Default::default(),
+            export_status: Default::default(),
+            expanded_preformatted: Default::default(),
+            highlighter: Arc::new(Highlighter::new()),
+            highlighted: Default::default(),
+            highlighting: Default::default(),
+            previews: Default::default(),
+            image_fit: Default::default(),
+            image_rotation: Default::default(),
+            rotated_image: Default::default(),
+            image_info: Default::default(),
+            image_status: Default::default(),
+            animations_paused: Default::default(),
+            feeds: Feeds::load(),
+            feeds_fetching: Default::default(),
+            feeds_queued: Default::default(),
+            feeds_refreshed_at: Default::default(),
+            feed_failures: Default::default(),
+            watches: Watches::load(),
+            page_changes: Default::default(),
+            show_outline: Default::default(),
+            heading_offsets: Default::default(),
+            show_find: Default::default(),
+            find_query: Default::default(),
+            found: Default::default(),
+            current_match: Default::default(),
+            find_error: Default::default(),
         }
     }
 }
@@ -83,10 +206,424 @@ enum Message {
     OnPressError(String),
     OnSubmitInput,
     OnChangeInput(String),
-    InputExpected(Url, Response),
+    InputExpected(Url, Option<String>), // url, prompt
+    OpenedExternally(Url, String),      // url, viewer command
+    ConfirmLaunch,
+    CancelLaunch,
+    CopyPageAsText,
+    SettingsButtonPressed,
+    SettingsChanged(Settings),
+    TogglePreformatted(usize), // line index
+    InlineImagesButtonPressed,
+    ImageDecoded(Url, Option<Picture>), // image url, decoded image
+    ImageFitChanged(ImageFit),
+    RotateImage,
+    Rotated(Url, u8, Result<Picture, String>), // image url, quarter turns, the turned image
+    SaveImage,
+    ToggleAnimations,
+    AnimationTick(Instant),
+    SubscribeButtonPressed,
+    // the subscribed feeds and watched pages fetched, or why they couldn't be
+    Refreshed(
+        Vec<(Url, Result<Feed, String>)>,
+        Vec<(Url, Result<Document, String>)>,
+    ),
+    WatchButtonPressed,
+    HideChanges,
+    ExportButtonPressed,
+    Export(Format),
+    Exported(Result<PathBuf, String>), // the saved file, or why it couldn't be
+    OutlineButtonPressed,
+    HeadingPressed(usize),              // line index
+    HeadingsLaidOut(Vec<(usize, f32)>), // line index, offset in the scrollable
+    WindowResized,
+    ScrollToHeading(usize), // line index
+    FindOpened,
+    FindClosed,
+    FindQueryChanged(Query),
+    FindNext,
+    FindPrevious,
+    MatchLaidOut(Vec<(usize, f32)>), // line index, offset in the scrollable
+    Highlighted(u64, HashMap<usize, HighlightedBlock>), // highlighting, blocks by line index
+}
+
+impl Message {
+    /// Adapts the result of `Client::fetch`, handing unsupported content to
+    /// the external viewers configured in `mailcap`.
+    fn from_fetch(result: anyhow::Result<FetchOutcome>, mailcap: &Mailcap) -> Self {
+        let outcome = match result {
+            Ok(x) => x,
+            Err(e) => return Message::Error(format!("fetch: {e}")),
+        };
+
+        match outcome {
+            FetchOutcome::Page(url, content) => Message::Loaded(url, Some(content.document)),
+            FetchOutcome::Unsupported(url, mime, body) => {
+                let Some(entry) = mailcap.find(&mime) else {
+                    return Message::Error(format!("unsupported MIME type: {mime}"));
+                };
+                match entry.launch(&url, &mime, &body) {
+                    Ok(()) => Message::OpenedExternally(url, entry.command.clone()),
+                    Err(e) => Message::Error(format!("failed to launch `{}`: {e}", entry.command)),
+                }
+            }
+            // TODO -> blur the input on the client for sensitive input
+            FetchOutcome::Input { url, prompt, .. } => Message::InputExpected(url, prompt),
+            FetchOutcome::Failure(_, status, ctx) => {
+                Message::Error(format!("Error status: {status:?}: {ctx:?}"))
+            }
+        }
+    }
+}
+
+impl Message {
+    /// Decodes the image fetched for a preview.
+    fn from_preview(url: Url, result: anyhow::Result<Exchange>) -> Self {
+        let data = match result {
+            Ok(Exchange { response, .. }) if response.status == Status::Success => {
+                let is_image = response
+                    .ctx
+                    .as_deref()
+                    .and_then(|x| x.parse::<mime::Mime>().ok())
+                    .is_some_and(|x| x.type_() == mime::IMAGE);
+                if is_image {
+                    response.body
+                } else {
+                    log::info!("Preview: {url} isn't an image: {:?}", response.ctx);
+                    None
+                }
+            }
+            Ok(Exchange { response, .. }) => {
+                log::info!("Preview: {url}: {:?} {:?}", response.status, response.ctx);
+                None
+            }
+            Err(e) => {
+                log::info!("Preview: {url}: {e}");
+                None
+            }
+        };
+        Message::ImageDecoded(url, data.map(|x| Picture::new(&x)))
+    }
 }
 
 impl GeminiClient {
+    /// Whether image links on `url`'s page get previews.
+    fn inline_images_enabled(&self, url: &Url) -> bool {
+        url.host_str()
+            .is_some_and(|host| self.settings.inline_images_on(host))
+    }
+
+    /// Fetches previews for the image links on the current page, one at a
+    /// time, instead of any still being fetched. Only images on the page's
+    /// own capsule are fetched.
+    fn load_previews(&mut self) -> Task<Message> {
+        self.previews = None;
+        let Some(doc) = &self.document else {
+            return Task::none();
+        };
+        if !self.inline_images_enabled(&doc.url) {
+            return Task::none();
+        }
+
+        let urls: Vec<Url> = doc
+            .lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Link(url, _) => Some(url),
+                _ => None,
+            })
+            .filter(|url| {
+                url.scheme() == "gemini"
+                    && url
+                        .host_str()
+                        .zip(doc.url.host_str())
+                        .is_some_and(|(a, b)| a.eq_ignore_ascii_case(b))
+                    && guess_mime(url).is_some_and(|x| x.type_() == mime::IMAGE)
+                    && !self.images.contains(url)
+            })
+            .take(MAX_PREVIEWS)
+            .cloned()
+            .collect();
+
+        let client = self
+            .client
+            .clone()
+            .with_max_response_size(self.settings.inline_image_max_kib.saturating_mul(1024));
+        let (task, handle) = urls
+            .into_iter()
+            .map(|url| {
+                let client = client.clone();
+                on_thread(move || {
+                    let result = client.request(&url, None);
+                    Message::from_preview(url, result)
+                })
+            })
+            .fold(Task::none(), Task::chain)
+            .abortable();
+        self.previews = Some(handle.abort_on_drop());
+        task
+    }
+
+    /// Decodes the current document if it's an image response that isn't
+    /// decoded yet.
+    fn decode_image_response(&self) -> Task<Message> {
+        let (Some(data), Some(doc)) = (self.image_response().cloned(), &self.document) else {
+            return Task::none();
+        };
+        if self.images.contains(&doc.url) {
+            return Task::none();
+        }
+        let url = doc.url.clone();
+        on_thread(move || {
+            let picture = Picture::new(&data);
+            Message::ImageDecoded(url, Some(picture))
+        })
+    }
+
+    /// The data of the current document, if it's an image response.
+    fn image_response(&self) -> Option<&Arc<[u8]>> {
+        match self.document.as_ref()?.lines.as_slice() {
+            [Line::Image(data)] => Some(data),
+            _ => None,
+        }
+    }
+
+    fn push_history(&mut self) {
+        if let Some(doc) = self.document.clone() {
+            log::info!("PageLoad: adding {:?} to history", doc.url);
+            self.history_back.push((doc, self.scroll_position));
+            self.history_forward.clear();
+        }
+    }
+
+    /// Internal pages, which are generated instead of fetched. The only one
+    /// is `about:feeds`, whose `?refresh` and `?read-all` links act on it
+    /// without leaving it.
+    fn about_page(&mut self, url: &Url) -> Task<Message> {
+        if url.path() != "feeds" {
+            return Task::done(Message::Error(format!("no such page: {url}")));
+        }
+        match url.query() {
+            Some("refresh") => {
+                let refresh_task = self.refresh_feeds();
+                Task::batch([refresh_task, self.show_feeds()])
+            }
+            Some("read-all") => {
+                self.feeds.mark_all_read();
+                Task::batch([self.save_feeds(), self.show_feeds()])
+            }
+            _ => {
+                self.push_history();
+                let scroll_task = scrollable::scroll_to(
+                    self.scroll_id.clone(),
+                    AbsoluteOffset { x: 0.0, y: 0.0 },
+                );
+                self.show_feeds().chain(scroll_task)
+            }
+        }
+    }
+
+    /// Shows `about:feeds`, without adding the current page to the history.
+    fn show_feeds(&self) -> Task<Message> {
+        let status = if !self.feeds_fetching.is_empty() || !self.feeds_queued.is_empty() {
+            "Refreshing…".to_string()
+        } else if let Some(refreshed_at) = self.feeds_refreshed_at {
+            let minutes = refreshed_at.elapsed().as_secs() / 60;
+            match self.feed_failures {
+                0 => format!("Refreshed {minutes} minutes ago."),
+                n => format!("Refreshed {minutes} minutes ago, but {n} failed to load."),
+            }
+        } else {
+            "Not refreshed yet.".to_string()
+        };
+        let mut doc = self.feeds.to_document(&status);
+        doc.lines.push(Line::Text(String::new()));
+        doc.lines.extend(self.watches.to_lines());
+        Task::done(Message::Loaded(doc.url.clone(), Some(doc)))
+    }
+
+    /// Fetches every subscribed feed and watched page that isn't already
+    /// being fetched or waiting to be.
+    fn refresh_feeds(&mut self) -> Task<Message> {
+        let urls: Vec<Url> = self
+            .feeds
+            .urls()
+            .chain(self.watches.urls())
+            .filter(|x| !self.feeds_fetching.contains(x) && !self.feeds_queued.contains(x))
+            .cloned()
+            .collect();
+        self.feeds_queued.extend(urls);
+        self.feeds_refreshed_at = Some(Instant::now());
+        self.feed_failures = 0;
+        self.fetch_queued_feeds()
+    }
+
+    /// Fetches the queued feeds and watched pages, one host at a time so
+    /// each host only gets one request at once. Those on a host that's still
+    /// being fetched from stay queued until it's done.
+    fn fetch_queued_feeds(&mut self) -> Task<Message> {
+        let busy: HashSet<Option<&str>> = self.feeds_fetching.iter().map(Url::host_str).collect();
+        let (ready, waiting): (Vec<Url>, Vec<Url>) = std::mem::take(&mut self.feeds_queued)
+            .into_iter()
+            // unsubscribed or unwatched while waiting
+            .filter(|x| self.feeds.is_subscribed(x) || self.watches.is_watched(x))
+            .partition(|x| !busy.contains(&x.host_str()));
+        self.feeds_queued = waiting;
+        let hosts = feeds::by_host(&ready);
+        self.feeds_fetching.extend(ready);
+        Task::batch(hosts.into_iter().map(|urls| {
+            let client = self.client.clone();
+            let (feed_urls, page_urls): (Vec<_>, Vec<_>) =
+                urls.into_iter().partition(|x| self.feeds.is_subscribed(x));
+            // the waits between requests would hold up the executor
+            on_thread(move || {
+                let feeds = feeds::fetch_all(&client, &feed_urls);
+                Message::Refreshed(feeds, watches::fetch_all(&client, &page_urls))
+            })
+        }))
+    }
+
+    fn save_watches(&self) -> Task<Message> {
+        match self.watches.save() {
+            Ok(()) => Task::none(),
+            Err(e) => Task::done(Message::Error(format!("failed to save watches: {e}"))),
+        }
+    }
+
+    fn save_feeds(&self) -> Task<Message> {
+        match self.feeds.save() {
+            Ok(()) => Task::none(),
+            Err(e) => Task::done(Message::Error(format!("failed to save feeds: {e}"))),
+        }
+    }
+
+    /// The document shown: the current one, or its changes if it's watched.
+    fn shown_document(&self) -> Option<&Document> {
+        self.page_changes
+            .as_ref()
+            .map(|(doc, _)| doc)
+            .or(self.document.as_ref())
+    }
+
+    /// Finds where the headings in the outline have been laid out, to scroll
+    /// to them and highlight the one scrolled to.
+    fn lay_out_headings(&self) -> Task<Message> {
+        let Some(doc) = self.shown_document().filter(|_| self.show_outline) else {
+            return Task::none();
+        };
+        let lines: Vec<_> = outline::headings(doc).iter().map(|x| x.line).collect();
+        if lines.is_empty() {
+            return Task::none();
+        }
+        document_view::line_offsets(CONTENT_ID, &lines).map(Message::HeadingsLaidOut)
+    }
+
+    /// Searches the shown document for the find query, starting again from
+    /// the first match.
+    fn search(&mut self) {
+        self.found.clear();
+        self.current_match = None;
+        self.find_error = None;
+        let Some(doc) = self.shown_document() else {
+            return;
+        };
+        match find::find(doc, &self.find_query) {
+            Ok(found) => {
+                self.current_match = (!found.is_empty()).then_some(0);
+                self.found = found;
+            }
+            Err(e) => self.find_error = Some(e.to_string()),
+        }
+    }
+
+    /// Scrolls to the line of the current match, expanding it if it's a
+    /// collapsed preformatted block.
+    fn scroll_to_match(&mut self) -> Task<Message> {
+        let Some(line) = self
+            .current_match
+            .and_then(|i| self.found.get(i))
+            .map(|x| x.line)
+        else {
+            return Task::none();
+        };
+        let preformatted = self
+            .shown_document()
+            .and_then(|doc| doc.lines.get(line))
+            .is_some_and(|x| matches!(x, Line::PreFormatted(..)));
+        if preformatted {
+            self.expanded_preformatted.insert(line);
+        }
+        document_view::line_offsets(CONTENT_ID, &[line]).map(Message::MatchLaidOut)
+    }
+
+    /// The animated images on the current page, including link previews.
+    fn animations(&mut self) -> Vec<&mut Animation> {
+        if self.document.is_none() {
+            return Vec::new();
+        }
+        self.images
+            .pictures_mut()
+            .chain(&mut self.rotated_image)
+            .filter_map(Picture::animation_mut)
+            .collect()
+    }
+
+    fn has_animations(&self) -> bool {
+        if self.document.is_none() {
+            return false;
+        }
+        self.images
+            .pictures()
+            .chain(&self.rotated_image)
+            .any(|picture| matches!(picture, Picture::Animated(_)))
+    }
+
+    fn subscription(&self) -> Subscription<Message> {
+        let mut subscriptions = vec![keyboard::listen().filter_map(find_key)];
+        if self.show_outline {
+            subscriptions.push(window::resize_events().map(|_| Message::WindowResized));
+        }
+        if !self.animations_paused && self.has_animations() {
+            subscriptions.push(window::frames().map(Message::AnimationTick));
+        }
+        Subscription::batch(subscriptions)
+    }
+
+    /// Styles the current document's preformatted blocks, either from the
+    /// ANSI escapes in them or by syntax highlighting. A block's alt text
+    /// names its language, falling back to the document's.
+    fn highlight_document(&mut self) -> Task<Message> {
+        let Some(doc) = self.document.clone() else {
+            return Task::none();
+        };
+        self.highlighting += 1;
+        let highlighting = self.highlighting;
+        let highlighter = self.highlighter.clone();
+        let strip_ansi = self.settings.strip_ansi;
+        on_thread(move || {
+            let highlighted = doc
+                .lines
+                .iter()
+                .enumerate()
+                .filter_map(|(i, line)| match line {
+                    Line::PreFormatted(s, _) if ansi::has_escapes(s) => {
+                        if strip_ansi {
+                            Some((i, HighlightedBlock::plain(ansi::strip(s))))
+                        } else {
+                            Some((i, HighlightedBlock::from_ansi(s)))
+                        }
+                    }
+                    Line::PreFormatted(s, alt) => {
+                        let language = alt.as_ref().or(doc.language.as_ref())?;
+                        Some((i, highlighter.highlight(language, s)?))
+                    }
+                    _ => None,
+                })
+                .collect();
+            Message::Highlighted(highlighting, highlighted)
+        })
+    }
+
     fn update(&mut self, message: Message) -> Task<Message> {
         match message {
             Message::UriChanged(uri) => {
@@ -94,21 +631,30 @@ impl GeminiClient {
             }
             Message::PageLoad(url) => {
                 log::info!("PageLoad: opening url: {url:?}");
-                if url.scheme() != "gemini" {
-                    if let Err(e) = opener::open(url.to_string()) {
-                        return Task::done(Message::Error(e.to_string()));
-                    }
-                    return Task::none();
+                if url.scheme() == "about" {
+                    return self.about_page(&url);
                 }
-                if let Some(doc) = self.document.clone() {
-                    log::info!("PageLoad: adding {:?} to history", doc.url);
-                    self.history_back.push((doc, self.scroll_position));
-                    self.history_forward.clear();
+                let handler = match self.schemes.resolve(&url) {
+                    Ok(x) => x.clone(),
+                    Err(e) => return Task::done(Message::Error(e.to_string())),
+                };
+                if handler.is_external() {
+                    // never launch another application without asking first
+                    self.pending_launch = Some((url, handler));
+                    return Task::none();
                 }
+                let proxy = match handler {
+                    SchemeHandler::Proxy(proxy) => Some(proxy),
+                    _ => None,
+                };
+                self.push_history();
                 let load_task = {
                     let url = url.clone();
-                    let client = self.client;
-                    Task::future(async move { client.load_page(&url) })
+                    let client = self.client.clone();
+                    let mailcap = self.mailcap.clone();
+                    on_thread(move || {
+                        Message::from_fetch(client.fetch(&url, proxy.as_ref()), &mailcap)
+                    })
                 };
 
                 // we only want to reset the scroll if the pageload is successful
@@ -139,7 +685,67 @@ impl GeminiClient {
             }
             Message::Loaded(url, document) => {
                 self.uri = url.to_string();
+                if let Some(doc) = &document {
+                    let urls = page_urls(doc);
+                    self.images.retain(|url| urls.contains(url));
+                }
                 self.document = document;
+                self.expanded_preformatted.clear();
+                self.image_fit = ImageFit::default();
+                self.image_rotation = 0;
+                self.rotated_image = None;
+                self.image_info = self.image_response().map(|data| ImageInfo::read(data));
+                self.image_status = None;
+                self.animations_paused = self.settings.reduce_motion;
+                self.highlighted.clear();
+                let highlight_task = self.highlight_document();
+                let mut save_task = Task::none();
+                if self.feeds.mark_read(&url) {
+                    save_task = self.save_feeds();
+                }
+                self.page_changes = None;
+                if let Some(doc) = self
+                    .document
+                    .as_ref()
+                    .filter(|x| self.watches.is_watched(&x.url))
+                {
+                    match self.watches.visit(doc) {
+                        Ok(changes) => {
+                            self.page_changes = changes.map(|changes| {
+                                let (changes, lines): (Vec<_>, Vec<_>) =
+                                    changes.into_iter().unzip();
+                                (Document::from_lines(&doc.url, lines), changes)
+                            });
+                            save_task = Task::batch([save_task, self.save_watches()]);
+                        }
+                        Err(e) => {
+                            save_task = Task::batch([
+                                save_task,
+                                Task::done(Message::Error(format!("failed to compare page: {e}"))),
+                            ]);
+                        }
+                    }
+                }
+                let refresh_task = if self
+                    .feeds_refreshed_at
+                    .is_none_or(|x| x.elapsed() >= FEEDS_REFRESH_INTERVAL)
+                {
+                    self.refresh_feeds()
+                } else {
+                    Task::none()
+                };
+                self.heading_offsets.clear();
+                if self.show_find {
+                    self.search();
+                }
+                return Task::batch([
+                    self.decode_image_response(),
+                    self.load_previews(),
+                    save_task,
+                    refresh_task,
+                    highlight_task,
+                    self.lay_out_headings(),
+                ]);
             }
             Message::Error(e) => {
                 // TODO - client error handling, maybe a modal?
@@ -182,6 +788,10 @@ impl GeminiClient {
             Message::Scrolled(absolute_offset) => {
                 self.scroll_position = absolute_offset;
             }
+            Message::WindowResized => {
+                // rewrapping the text moves the headings
+                return self.lay_out_headings();
+            }
             Message::HomeButtonPressed => {
                 // TODO -> Custom home page
                 return Task::done(Message::PageLoad(
@@ -210,13 +820,299 @@ impl GeminiClient {
             Message::OnChangeInput(s) => {
                 self.input_text = s;
             }
-            Message::InputExpected(url, res) => {
-                log::info!("InputExpected: {url:?}, {res:?}");
-                let prompt = res
-                    .ctx
-                    .unwrap_or("Input Expected (no information provided)".into());
+            Message::InputExpected(url, prompt) => {
+                log::info!("InputExpected: {url:?}, {prompt:?}");
+                let prompt = prompt.unwrap_or("Input Expected (no information provided)".into());
                 self.input_request = Some(InputRequest::new(url, prompt));
             }
+            Message::OpenedExternally(url, command) => {
+                log::info!("OpenedExternally: {url} with `{command}`");
+            }
+            Message::ConfirmLaunch => {
+                if let Some((url, handler)) = self.pending_launch.take() {
+                    log::info!("ConfirmLaunch: opening {url} with {handler}");
+                    if let Err(e) = handler.launch(&url) {
+                        return Task::done(Message::Error(e.to_string()));
+                    }
+                }
+            }
+            Message::CancelLaunch => {
+                self.pending_launch = None;
+            }
+            Message::CopyPageAsText => {
+                if let Some(doc) = &self.document {
+                    return iced::clipboard::write(TextRenderer::new().render(doc));
+                }
+            }
+            Message::SettingsButtonPressed => {
+                self.show_settings = !self.show_settings;
+            }
+            Message::SettingsChanged(settings) => {
+                self.settings = settings;
+                // collapsing or shrinking blocks and previews move the headings
+                let mut tasks = vec![self.highlight_document(), self.lay_out_headings()];
+                if let Err(e) = self.settings.save() {
+                    tasks.push(Task::done(Message::Error(format!(
+                        "failed to save settings: {e}"
+                    ))));
+                }
+                return Task::batch(tasks);
+            }
+            Message::InlineImagesButtonPressed => {
+                let Some(host) = self.document.as_ref().and_then(|doc| doc.url.host_str()) else {
+                    return Task::none();
+                };
+                self.settings.toggle_inline_image_host(host);
+                if let Err(e) = self.settings.save() {
+                    return Task::done(Message::Error(format!("failed to save settings: {e}")));
+                }
+                return Task::batch([self.load_previews(), self.lay_out_headings()]);
+            }
+            Message::ImageDecoded(url, picture) => {
+                let on_page = self
+                    .document
+                    .as_ref()
+                    .is_some_and(|doc| page_urls(doc).contains(&url));
+                if let Some(picture) = picture.filter(|_| on_page) {
+                    self.images.insert(url, picture);
+                    // the image pushes the lines below it down
+                    return self.lay_out_headings();
+                }
+            }
+            Message::ImageFitChanged(fit) => {
+                self.image_fit = fit;
+            }
+            Message::RotateImage => {
+                let (Some(data), Some(doc)) = (self.image_response().cloned(), &self.document)
+                else {
+                    return Task::none();
+                };
+                self.image_rotation = (self.image_rotation + 1) % 4;
+                if self.image_rotation == 0 {
+                    self.rotated_image = None;
+                    return Task::none();
+                }
+                let (url, rotation) = (doc.url.clone(), self.image_rotation);
+                return on_thread(move || {
+                    let result = Picture::decode(&data, rotation).map_err(|e| e.to_string());
+                    Message::Rotated(url, rotation, result)
+                });
+            }
+            Message::Rotated(url, rotation, result) => {
+                // the image may have been turned again or left since
+                let current = self.document.as_ref().is_some_and(|doc| doc.url == url);
+                if !current || rotation != self.image_rotation {
+                    return Task::none();
+                }
+                match result {
+                    Ok(picture) => self.rotated_image = Some(picture),
+                    Err(e) => return Task::done(Message::Error(format!("failed to rotate: {e}"))),
+                }
+            }
+            Message::SaveImage => {
+                let (Some(data), Some(info), Some(doc)) =
+                    (self.image_response(), &self.image_info, &self.document)
+                else {
+                    return Task::none();
+                };
+                let Some(dir) = config::download_dir() else {
+                    return Task::done(Message::Error("no download directory".to_string()));
+                };
+                match image_view::save(data, info, &doc.url, &dir) {
+                    Ok(path) => self.image_status = Some(format!("saved to {}", path.display())),
+                    Err(e) => return Task::done(Message::Error(format!("failed to save: {e}"))),
+                }
+            }
+            Message::ToggleAnimations => {
+                self.animations_paused = !self.animations_paused;
+                if self.animations_paused {
+                    self.animations().into_iter().for_each(Animation::pause);
+                }
+            }
+            Message::AnimationTick(now) => {
+                for animation in self.animations() {
+                    animation.tick(now);
+                }
+            }
+            Message::SubscribeButtonPressed => {
+                let Some(url) = self.document.as_ref().map(|doc| doc.url.clone()) else {
+                    return Task::none();
+                };
+                if self.feeds.is_subscribed(&url) {
+                    self.feeds.unsubscribe(&url);
+                    return self.save_feeds();
+                }
+                self.feeds.subscribe(url.clone());
+                // fetched once nothing else is being fetched from its host
+                if !self.feeds_queued.contains(&url) {
+                    self.feeds_queued.push(url);
+                }
+                return Task::batch([self.save_feeds(), self.fetch_queued_feeds()]);
+            }
+            Message::Refreshed(feeds, pages) => {
+                for url in feeds.iter().map(|x| &x.0).chain(pages.iter().map(|x| &x.0)) {
+                    self.feeds_fetching.remove(url);
+                }
+                let today = feeds::today();
+                for (url, result) in feeds {
+                    match result {
+                        Ok(feed) => {
+                            let added = self.feeds.merge(&url, &feed, &today);
+                            log::info!("Refreshed: {added} new entries in {url}");
+                        }
+                        Err(e) => {
+                            log::warn!("Refreshed: {url}: {e}");
+                            self.feed_failures += 1;
+                        }
+                    }
+                }
+                for (url, result) in pages {
+                    match result.and_then(|doc| self.watches.check(&doc).map_err(|e| e.to_string()))
+                    {
+                        Ok(changed) => log::info!("Refreshed: {url} changed: {changed}"),
+                        Err(e) => {
+                            log::warn!("Refreshed: {url}: {e}");
+                            self.feed_failures += 1;
+                        }
+                    }
+                }
+                // what was waiting for this host to be done
+                let fetch_task = self.fetch_queued_feeds();
+                let save_task = Task::batch([self.save_feeds(), self.save_watches(), fetch_task]);
+                // refresh the feeds page if it's open
+                if self
+                    .document
+                    .as_ref()
+                    .is_some_and(|doc| doc.url.as_str() == FEEDS_URL)
+                {
+                    return Task::batch([save_task, self.show_feeds()]);
+                }
+                return save_task;
+            }
+            Message::WatchButtonPressed => {
+                let Some(doc) = &self.document else {
+                    return Task::none();
+                };
+                let result = if self.watches.is_watched(&doc.url) {
+                    self.watches.unwatch(&doc.url)
+                } else {
+                    self.watches.watch(doc)
+                };
+                if let Err(e) = result {
+                    return Task::done(Message::Error(format!("failed to watch page: {e}")));
+                }
+                return self.save_watches();
+            }
+            Message::HideChanges => {
+                self.page_changes = None;
+                self.expanded_preformatted.clear();
+                if self.show_find {
+                    self.search();
+                }
+                return self.lay_out_headings();
+            }
+            Message::ExportButtonPressed => {
+                self.show_export = !self.show_export;
+                if !self.exporting {
+                    self.export_status = None;
+                }
+            }
+            Message::Export(format) => {
+                let Some(doc) = self.document.clone() else {
+                    return Task::none();
+                };
+                self.exporting = true;
+                self.export_status = Some(match format {
+                    Format::EpubWithLinks => "Fetching linked pages…".to_string(),
+                    _ => "Exporting…".to_string(),
+                });
+                let client = self.client.clone();
+                // fetching linked pages waits between requests
+                return on_thread(move || {
+                    Message::Exported(
+                        export::export(&client, &doc, format).map_err(|e| e.to_string()),
+                    )
+                });
+            }
+            Message::Exported(result) => {
+                self.exporting = false;
+                match result {
+                    Ok(path) => self.export_status = Some(format!("Saved to {}", path.display())),
+                    Err(e) => {
+                        self.export_status = None;
+                        return Task::done(Message::Error(format!("failed to export: {e}")));
+                    }
+                }
+            }
+            Message::OutlineButtonPressed => {
+                self.show_outline = !self.show_outline;
+                self.heading_offsets.clear();
+                return self.lay_out_headings();
+            }
+            Message::HeadingPressed(line) => {
+                return self
+                    .lay_out_headings()
+                    .chain(Task::done(Message::ScrollToHeading(line)));
+            }
+            Message::HeadingsLaidOut(offsets) => {
+                self.heading_offsets = offsets;
+            }
+            Message::ScrollToHeading(line) => {
+                let Some(&(_, y)) = self.heading_offsets.iter().find(|(i, _)| *i == line) else {
+                    return Task::none();
+                };
+                self.scroll_position = AbsoluteOffset { x: 0.0, y };
+                return scrollable::scroll_to(self.scroll_id.clone(), self.scroll_position);
+            }
+            Message::FindOpened => {
+                self.show_find = true;
+                self.search();
+                return operation::focus(FIND_INPUT).chain(operation::select_all(FIND_INPUT));
+            }
+            Message::FindClosed => {
+                self.show_find = false;
+                self.found.clear();
+                self.current_match = None;
+                self.find_error = None;
+            }
+            Message::FindQueryChanged(query) => {
+                self.find_query = query;
+                self.search();
+                return self.scroll_to_match();
+            }
+            Message::FindNext => {
+                let count = self.found.len();
+                self.current_match = self.current_match.map(|x| (x + 1) % count);
+                return self.scroll_to_match();
+            }
+            Message::FindPrevious => {
+                let count = self.found.len();
+                self.current_match = self.current_match.map(|x| (x + count - 1) % count);
+                return self.scroll_to_match();
+            }
+            Message::MatchLaidOut(offsets) => {
+                let Some(&(_, y)) = offsets.first() else {
+                    return Task::none();
+                };
+                // leave the lines before it in view, for context
+                self.scroll_position = AbsoluteOffset {
+                    x: 0.0,
+                    y: (y - MATCH_MARGIN).max(0.0),
+                };
+                return scrollable::scroll_to(self.scroll_id.clone(), self.scroll_position);
+            }
+            Message::Highlighted(highlighting, highlighted) => {
+                // a later highlighting, of another page or settings, wins
+                if highlighting == self.highlighting {
+                    self.highlighted = highlighted;
+                }
+            }
+            Message::TogglePreformatted(i) => {
+                if !self.expanded_preformatted.remove(&i) {
+                    self.expanded_preformatted.insert(i);
+                }
+                return self.lay_out_headings();
+            }
         }
         Task::none()
     }
@@ -232,34 +1128,207 @@ impl GeminiClient {
                     .on_submit(Message::GoButtonPressed),
             )
             .push(button("Go").on_press(Message::GoButtonPressed))
+            .push(button(GeminiText::new("📋").view()).on_press(Message::CopyPageAsText))
+            .push(self.settings.inline_images.then(|| {
+                let enabled = self
+                    .document
+                    .as_ref()
+                    .is_some_and(|doc| self.inline_images_enabled(&doc.url));
+                button(GeminiText::new("🖼").view())
+                    .style(if enabled {
+                        button::primary
+                    } else {
+                        button::secondary
+                    })
+                    .on_press(Message::InlineImagesButtonPressed)
+            }))
+            .push(
+                self.document
+                    .as_ref()
+                    .filter(|doc| doc.url.scheme() != "about")
+                    .map(|doc| {
+                        let subscribed = self.feeds.is_subscribed(&doc.url);
+                        button(GeminiText::new("⭐").view())
+                            .style(if subscribed {
+                                button::primary
+                            } else {
+                                button::secondary
+                            })
+                            .on_press(Message::SubscribeButtonPressed)
+                    }),
+            )
+            .push(
+                self.document
+                    .as_ref()
+                    .filter(|doc| doc.url.scheme() != "about")
+                    .map(|doc| {
+                        let watched = self.watches.is_watched(&doc.url);
+                        button(GeminiText::new("👁").view())
+                            .style(if watched {
+                                button::primary
+                            } else {
+                                button::secondary
+                            })
+                            .on_press(Message::WatchButtonPressed)
+                    }),
+            )
+            .push(
+                button(GeminiText::new("📰").view()).on_press(Message::PageLoad(
+                    Url::parse(FEEDS_URL).expect("Should never fail"),
+                )),
+            )
+            .push(self.has_animations().then(|| {
+                let label = if self.animations_paused { "▶" } else { "⏸" };
+                button(GeminiText::new(label).view()).on_press(Message::ToggleAnimations)
+            }))
+            .push(self.document.is_some().then(|| {
+                button(GeminiText::new("☰").view())
+                    .style(if self.show_outline {
+                        button::primary
+                    } else {
+                        button::secondary
+                    })
+                    .on_press(Message::OutlineButtonPressed)
+            }))
+            .push(self.document.is_some().then(|| {
+                button(GeminiText::new("📤").view()).on_press(Message::ExportButtonPressed)
+            }))
+            .push(button(GeminiText::new("⚙").view()).on_press(Message::SettingsButtonPressed))
+    }
+
+    /// The sidebar listing the shown document's headings, if it's open.
+    fn outline(&self) -> Option<Element<'_, Message>> {
+        let doc = self
+            .shown_document()
+            .filter(|_| self.show_outline && self.image_response().is_none())?;
+        let current = outline::current_heading(&self.heading_offsets, self.scroll_position.y);
+        Some(Outline::new(doc, current).view(Message::HeadingPressed))
+    }
+
+    /// The bar for finding text on the page, if it's open.
+    fn find_bar(&self) -> Option<Element<'_, Message>> {
+        if !self.show_find {
+            return None;
+        }
+        let find_bar = FindBar::new(&self.find_query, self.found.len(), self.current_match)
+            .error(self.find_error.as_deref());
+        Some(find_bar.view(
+            Message::FindQueryChanged,
+            Message::FindNext,
+            Message::FindPrevious,
+            Message::FindClosed,
+        ))
     }
 
     fn body(&self) -> Element<'_, Message> {
-        if let Some(doc) = &self.document {
+        let image = self
+            .image_info
+            .as_ref()
+            .zip(self.rotated_image.as_ref().or_else(|| {
+                self.document
+                    .as_ref()
+                    .and_then(|doc| self.images.get(&doc.url))
+            }));
+        if let Some((info, picture)) = image {
+            ImageView::new(info, picture)
+                .fit(self.image_fit)
+                .status(self.image_status.as_deref())
+                .view(
+                    Message::ImageFitChanged,
+                    Message::RotateImage,
+                    Message::SaveImage,
+                )
+        } else if let Some(doc) = &self.document {
             // TODO -> make the max width customisable
-            let doc_view = container(doc.view(|url| Message::ButtonPressed(url.clone())))
+            let shown = self.page_changes.as_ref().map_or(doc, |(x, _)| x);
+            let mut doc_view = DocumentView::new(shown, &self.images, |url| {
+                Message::ButtonPressed(url.clone())
+            })
+            .shrink_preformatted(self.settings.shrink_preformatted)
+            .inline_images(self.inline_images_enabled(&doc.url))
+            .found(&self.found, self.current_match);
+            let mut banner = None;
+            if let Some((_, changes)) = &self.page_changes {
+                // highlighting is by line index in `doc`, so is left out
+                doc_view = doc_view.changes(changes);
+                let count = |change| changes.iter().filter(|x| **x == change).count();
+                banner = Some(
+                    Row::new()
+                        .spacing(10)
+                        .align_y(iced::Center)
+                        .push(
+                            GeminiText::new(&format!(
+                                "Changed since you last saw it: {} lines added, {} removed",
+                                count(Change::Added),
+                                count(Change::Removed)
+                            ))
+                            .view(),
+                        )
+                        .push(
+                            button(GeminiText::new("Hide changes").view())
+                                .style(button::secondary)
+                                .on_press(Message::HideChanges),
+                        ),
+                );
+            } else {
+                doc_view = doc_view.highlighted(&self.highlighted);
+            }
+            if self.settings.collapse_preformatted {
+                doc_view = doc_view.collapse_preformatted(
+                    &self.expanded_preformatted,
+                    Message::TogglePreformatted,
+                );
+            }
+            let doc_view = container(column![].spacing(20).push(banner).push(doc_view.view()))
                 .width(1000)
                 .max_width(1000);
-            scrollable(container(doc_view).padding(20).center_x(Fill))
-                .on_scroll(|v| Message::Scrolled(v.absolute_offset()))
-                .id(self.scroll_id.clone())
-                .width(iced::Fill)
-                .height(iced::Fill)
-                .into()
+            scrollable(
+                container(doc_view)
+                    .id(CONTENT_ID)
+                    .padding(20)
+                    .center_x(Fill),
+            )
+            .on_scroll(|v| Message::Scrolled(v.absolute_offset()))
+            .id(self.scroll_id.clone())
+            .width(iced::Fill)
+            .height(iced::Fill)
+            .into()
         } else {
             text("no page").into()
         }
     }
 
     fn view(&self) -> Element<'_, Message> {
-        let base = column![self.url_bar(), self.body()].extend(self.errors.iter().enumerate().map(
-            |(i, err)| {
+        let base = column![self.url_bar()]
+            .push(self.find_bar())
+            .push(row![].push(self.outline()).push(self.body()))
+            .extend(self.errors.iter().enumerate().map(|(i, err)| {
                 ErrorDialog::new(err.to_string(), Message::HideErrorModal(i))
                     .view(Message::OnPressError(err.clone()))
-            },
-        ));
+            }));
 
-        if let Some(input_request) = &self.input_request {
+        if self.show_settings {
+            let settings_modal = SettingsModal::new(&self.settings);
+            Modal::new(
+                base,
+                settings_modal.view(Message::SettingsChanged, Message::SettingsButtonPressed),
+            )
+            .view()
+        } else if self.show_export {
+            let export_modal = ExportModal::new(self.export_status.as_deref(), self.exporting);
+            Modal::new(
+                base,
+                export_modal.view(Message::Export, Message::ExportButtonPressed),
+            )
+            .view()
+        } else if let Some((url, handler)) = &self.pending_launch {
+            let confirm_modal = ConfirmModal::new(format!("Open {url} with {handler}?"));
+            Modal::new(
+                base,
+                confirm_modal.view(Message::ConfirmLaunch, Message::CancelLaunch),
+            )
+            .view()
+        } else if let Some(input_request) = &self.input_request {
             let input_modal = input_request.modal();
             let modal = Modal::new(
                 base,
@@ -275,3 +1344,36 @@ impl GeminiClient {
         }
     }
 }
+
+/// Runs `f` on a thread of its own, keeping slow or blocking work like
+/// highlighting or waiting between requests off the executor.
+fn on_thread<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> Task<T> {
+    let (sender, receiver) = oneshot::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(f());
+    });
+    // the thread only drops `sender` without sending if `f` panicked
+    Task::future(async { receiver.await.ok() }).and_then(Task::done)
+}
+
+/// Ctrl+F opens the find bar, and Escape closes it.
+fn find_key(event: keyboard::Event) -> Option<Message> {
+    let keyboard::Event::KeyPressed { key, modifiers, .. } = event else {
+        return None;
+    };
+    match key.as_ref() {
+        keyboard::Key::Character("f") if modifiers.command() => Some(Message::FindOpened),
+        keyboard::Key::Named(keyboard::key::Named::Escape) => Some(Message::FindClosed),
+        _ => None,
+    }
+}
+
+/// The URLs whose images can appear on `doc`: its own, and those it links to.
+fn page_urls(doc: &Document) -> HashSet<&Url> {
+    std::iter::once(&doc.url)
+        .chain(doc.lines.iter().filter_map(|line| match line {
+            Line::Link(url, _) => Some(url),
+            _ => None,
+        }))
+        .collect()
+}
@@ -0,0 +1,44 @@
+use std::path::Path;
+
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum IdentityError {
+    #[error("identity: {0}")]
+    Pem(#[from] rustls::pki_types::pem::Error),
+    #[error("identity: no certificate found")]
+    MissingCertificate,
+}
+
+/// A client certificate and its private key, presented to servers that ask
+/// for one (status 60).
+#[derive(Debug)]
+pub struct Identity {
+    cert_chain: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+}
+
+impl Identity {
+    /// Reads the certificate chain and private key from PEM data holding both.
+    pub fn from_pem(pem: &[u8]) -> Result<Self, IdentityError> {
+        let cert_chain = CertificateDer::pem_slice_iter(pem).collect::<Result<Vec<_>, _>>()?;
+        if cert_chain.is_empty() {
+            return Err(IdentityError::MissingCertificate);
+        }
+        let key = PrivateKeyDer::from_pem_slice(pem)?;
+        Ok(Self { cert_chain, key })
+    }
+
+    pub fn from_pem_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Ok(Self::from_pem(&std::fs::read(path)?)?)
+    }
+
+    pub fn cert_chain(&self) -> &[CertificateDer<'static>] {
+        &self.cert_chain
+    }
+
+    pub fn key(&self) -> PrivateKeyDer<'static> {
+        self.key.clone_key()
+    }
+}
@@ -1,2 +1,3 @@
+pub mod identity;
 pub mod tofu_cert_verifier;
 pub mod tofu_socket;
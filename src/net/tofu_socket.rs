@@ -4,10 +4,13 @@ use std::{
     sync::{Arc, LazyLock},
 };
 
-use rustls::{RootCertStore, pki_types::ServerName};
+use rustls::{
+    RootCertStore,
+    pki_types::{CertificateDer, ServerName},
+};
 use url::Url;
 
-use crate::net::tofu_cert_verifier::TofuCertVerifier;
+use crate::net::{identity::Identity, tofu_cert_verifier::TofuCertVerifier};
 
 static ROOT_CERT_STORE: LazyLock<RootCertStore> =
     LazyLock::new(|| webpki_roots::TLS_SERVER_ROOTS.iter().cloned().collect());
@@ -22,15 +25,21 @@ impl TofuSocket {
     pub fn new<U: TryInto<Url> + std::fmt::Debug + Clone>(
         host: U,
         verifier: TofuCertVerifier,
+        identity: Option<&Identity>,
     ) -> anyhow::Result<Self>
     where
         <U as TryInto<Url>>::Error: std::error::Error + Send + Sync + 'static,
     {
         let host: Url = host.try_into()?;
 
-        let mut config = rustls::ClientConfig::builder()
-            .with_root_certificates(ROOT_CERT_STORE.clone())
-            .with_no_client_auth();
+        let builder =
+            rustls::ClientConfig::builder().with_root_certificates(ROOT_CERT_STORE.clone());
+        let mut config = match identity {
+            Some(identity) => {
+                builder.with_client_auth_cert(identity.cert_chain().to_vec(), identity.key())?
+            }
+            None => builder.with_no_client_auth(),
+        };
         config
             .dangerous()
             .set_certificate_verifier(Arc::new(verifier));
@@ -50,15 +59,32 @@ impl TofuSocket {
         Ok(TofuSocket { client, sock })
     }
 
-    pub fn request(&mut self, request: &[u8]) -> anyhow::Result<Vec<u8>> {
+    /// Sends `request` and reads the response, failing if it's longer than
+    /// `max_len` bytes.
+    pub fn request(&mut self, request: &[u8], max_len: Option<u64>) -> anyhow::Result<Vec<u8>> {
         let mut stream = rustls::Stream::new(&mut self.client, &mut self.sock);
 
         stream.write_all(request)?;
         stream.flush()?;
 
         let mut buf = Vec::new();
-        stream.read_to_end(&mut buf)?;
+        match max_len {
+            Some(max_len) => {
+                stream.take(max_len + 1).read_to_end(&mut buf)?;
+                if buf.len() as u64 > max_len {
+                    anyhow::bail!("TofuSocket: response is larger than {max_len} bytes");
+                }
+            }
+            None => {
+                stream.read_to_end(&mut buf)?;
+            }
+        }
 
         Ok(buf)
     }
+
+    /// The end-entity certificate the server presented, once the handshake is done.
+    pub fn peer_certificate(&self) -> Option<CertificateDer<'static>> {
+        self.client.peer_certificates()?.first().cloned()
+    }
 }
@@ -0,0 +1,191 @@
+use std::{collections::BTreeSet, fmt};
+
+use thiserror::Error;
+
+use crate::config::ConfigFile;
+
+#[derive(Error, Debug)]
+pub enum SettingsError {
+    #[error("line {0}: expected `<setting> = <value>`")]
+    MalformedLine(usize),
+    #[error("line {0}: unknown setting `{1}`")]
+    UnknownSetting(usize, String),
+    #[error("line {0}: invalid value `{1}`")]
+    InvalidValue(usize, String),
+}
+
+/// User preferences, stored as `<setting> = <value>` lines in `settings` in
+/// the config directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    /// Hide preformatted blocks that have alt text behind it, until clicked.
+    pub collapse_preformatted: bool,
+    /// Remove ANSI escape sequences from preformatted text instead of
+    /// rendering their colours.
+    pub strip_ansi: bool,
+    /// Shrink the text of preformatted blocks wider than the page to fit.
+    pub shrink_preformatted: bool,
+    /// Show previews of images below links to them, on the capsules in
+    /// `inline_image_hosts`.
+    pub inline_images: bool,
+    /// Images larger than this aren't previewed.
+    pub inline_image_max_kib: u64,
+    pub inline_image_hosts: BTreeSet<String>,
+    /// Start animated images paused.
+    pub reduce_motion: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            collapse_preformatted: false,
+            strip_ansi: false,
+            shrink_preformatted: false,
+            inline_images: false,
+            inline_image_max_kib: 1024,
+            inline_image_hosts: BTreeSet::new(),
+            reduce_motion: false,
+        }
+    }
+}
+
+impl Settings {
+    /// Whether previews are shown on `host`'s capsule. Hosts are compared
+    /// ignoring case, as `gemini:` URLs keep the case they're written in.
+    pub fn inline_images_on(&self, host: &str) -> bool {
+        self.inline_images && self.inline_image_hosts.contains(&host.to_ascii_lowercase())
+    }
+
+    /// Turns previews on `host`'s capsule on if they're off, and off if
+    /// they're on.
+    pub fn toggle_inline_image_host(&mut self, host: &str) {
+        let host = host.to_ascii_lowercase();
+        if !self.inline_image_hosts.remove(&host) {
+            self.inline_image_hosts.insert(host);
+        }
+    }
+}
+
+impl ConfigFile for Settings {
+    const NAME: &'static str = "settings";
+
+    type Error = SettingsError;
+
+    fn parse_line(&mut self, line_number: usize, line: &str) -> Result<(), SettingsError> {
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(SettingsError::MalformedLine(line_number));
+        };
+        let (key, value) = (key.trim(), value.trim());
+        let invalid = || SettingsError::InvalidValue(line_number, value.to_string());
+        let flag = || value.parse::<bool>().map_err(|_| invalid());
+
+        match key {
+            "collapse_preformatted" => self.collapse_preformatted = flag()?,
+            "strip_ansi" => self.strip_ansi = flag()?,
+            "shrink_preformatted" => self.shrink_preformatted = flag()?,
+            "inline_images" => self.inline_images = flag()?,
+            "inline_image_max_kib" => {
+                self.inline_image_max_kib = value.parse().map_err(|_| invalid())?;
+            }
+            "inline_image_hosts" => {
+                self.inline_image_hosts = value
+                    .split(',')
+                    .map(|x| x.trim().to_ascii_lowercase())
+                    .filter(|x| !x.is_empty())
+                    .collect();
+            }
+            "reduce_motion" => self.reduce_motion = flag()?,
+            x => return Err(SettingsError::UnknownSetting(line_number, x.to_string())),
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "collapse_preformatted = {}", self.collapse_preformatted)?;
+        writeln!(f, "strip_ansi = {}", self.strip_ansi)?;
+        writeln!(f, "shrink_preformatted = {}", self.shrink_preformatted)?;
+        writeln!(f, "inline_images = {}", self.inline_images)?;
+        writeln!(f, "inline_image_max_kib = {}", self.inline_image_max_kib)?;
+        let hosts: Vec<&str> = self.inline_image_hosts.iter().map(String::as_str).collect();
+        writeln!(f, "inline_image_hosts = {}", hosts.join(", "))?;
+        writeln!(f, "reduce_motion = {}", self.reduce_motion)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let (settings, errors) = Settings::parse(
+            "# comment\n\
+             \n\
+             collapse_preformatted = true\n\
+             inline_image_max_kib = 256\n\
+             reduce_motion = true\n\
+             inline_image_hosts = Example.com, ,other.example\n",
+        );
+        assert!(errors.is_empty());
+        assert!(settings.collapse_preformatted);
+        assert!(!settings.strip_ansi);
+        assert_eq!(settings.inline_image_max_kib, 256);
+        assert!(settings.reduce_motion);
+        assert_eq!(
+            settings.inline_image_hosts,
+            BTreeSet::from(["example.com".into(), "other.example".into()])
+        );
+        assert_eq!(Settings::parse(&settings.to_string()).0, settings);
+        assert_eq!(Settings::parse("").0, Settings::default());
+    }
+
+    // regression: hosts were toggled in the case they were written in, but
+    // lowercased when loaded, so a capsule lost its previews on restart
+    #[test]
+    fn test_toggle_inline_image_host() {
+        let mut settings = Settings {
+            inline_images: true,
+            ..Settings::default()
+        };
+        settings.toggle_inline_image_host("Example.com");
+        assert!(settings.inline_images_on("example.COM"));
+
+        let (loaded, errors) = Settings::parse(&settings.to_string());
+        assert!(errors.is_empty());
+        assert!(loaded.inline_images_on("Example.com"));
+
+        settings.toggle_inline_image_host("EXAMPLE.com");
+        assert!(!settings.inline_images_on("Example.com"));
+        assert!(settings.inline_image_hosts.is_empty());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let (settings, errors) = Settings::parse(
+            "collapse_preformatted\n\
+             strip_ansi = yes\n\
+             inline_image_max_kib = -1\n\
+             font_size = 12\n\
+             reduce_motion = true\n",
+        );
+        assert!(matches!(
+            errors[..],
+            [
+                SettingsError::MalformedLine(1),
+                SettingsError::InvalidValue(2, _),
+                SettingsError::InvalidValue(3, _),
+                SettingsError::UnknownSetting(4, _),
+            ]
+        ));
+        // the lines after the bad ones still apply
+        assert_eq!(
+            settings,
+            Settings {
+                reduce_motion: true,
+                ..Settings::default()
+            }
+        );
+    }
+}
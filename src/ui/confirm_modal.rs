@@ -0,0 +1,40 @@
+use iced::{
+    Element,
+    Length::{Fill, Shrink},
+    widget::{Column, Row, button, container},
+};
+
+use crate::ui::gemini_text::GeminiText;
+
+pub struct ConfirmModal {
+    prompt: String,
+}
+
+impl ConfirmModal {
+    pub fn new(prompt: String) -> Self {
+        Self { prompt }
+    }
+
+    pub fn view<'a, Message: Clone + 'a>(
+        self,
+        on_confirm: Message,
+        on_cancel: Message,
+    ) -> Element<'a, Message> {
+        let text_row = container(GeminiText::new(&self.prompt).view())
+            .center_x(Fill)
+            .center_y(Shrink)
+            .padding(10);
+
+        let button_row = container(
+            Row::new()
+                .push(button("Cancel").on_press(on_cancel))
+                .push(button("Open").on_press(on_confirm))
+                .spacing(10),
+        )
+        .center_x(Fill)
+        .center_y(Shrink)
+        .padding(10);
+
+        Column::new().push(text_row).push(button_row).into()
+    }
+}
@@ -0,0 +1,402 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+
+use iced::{
+    Color, ContentFit, Element, Font,
+    Length::{Fill, Shrink},
+    Rectangle, Task, Theme,
+    advanced::widget::{self, Id, Operation, operation::Outcome},
+    padding,
+    widget::{
+        Column, Container, button, container, responsive, rich_text, scrollable, span,
+        text::{Span, Wrapping},
+    },
+};
+use iced_gemini::gemini::{
+    ansi,
+    diff::Change,
+    find::Match,
+    gemtext::{Document, Line},
+};
+use url::Url;
+
+use crate::ui::{
+    gemini_link::GeminiLink, gemini_text::GeminiText, gemini_tooltip::GeminiTooltip,
+    highlight::HighlightedBlock, image_cache::ImageCache,
+};
+
+/// The indices of the expanded preformatted blocks, and the message toggling
+/// one.
+type Collapse<'a, Message> = (&'a HashSet<usize>, fn(usize) -> Message);
+
+pub struct DocumentView<'a, Message: Clone> {
+    document: &'a Document,
+    images: &'a ImageCache,
+    on_press_link: fn(&Url) -> Message,
+    collapse_preformatted: Option<Collapse<'a, Message>>,
+    highlighted: Option<&'a HashMap<usize, HighlightedBlock>>, // by line index
+    shrink_preformatted: bool,
+    wrap_preformatted: bool,
+    inline_images: bool,
+    changes: Option<&'a [Change]>, // by line index
+    // the matches of a search, in order, and the index of the current one
+    found: Option<(&'a [Match], Option<usize>)>,
+}
+
+impl<'a, Message: Clone + 'a> DocumentView<'a, Message> {
+    /// `images` holds the decoded pictures for image lines, keyed by the URL
+    /// they were loaded from.
+    pub fn new(
+        document: &'a Document,
+        images: &'a ImageCache,
+        on_press_link: fn(&Url) -> Message,
+    ) -> Self {
+        Self {
+            document,
+            images,
+            on_press_link,
+            collapse_preformatted: None,
+            highlighted: None,
+            shrink_preformatted: false,
+            wrap_preformatted: false,
+            inline_images: false,
+            changes: None,
+            found: None,
+        }
+    }
+
+    /// Shrinks the text of preformatted blocks too wide for the page, down
+    /// to a minimum size. Wider blocks still scroll horizontally.
+    pub fn shrink_preformatted(mut self, shrink_preformatted: bool) -> Self {
+        self.shrink_preformatted = shrink_preformatted;
+        self
+    }
+
+    /// Wraps the lines of preformatted blocks too wide for the page instead
+    /// of scrolling them, for when they can't be scrolled, e.g. when drawing
+    /// the page to an image.
+    pub fn wrap_preformatted(mut self, wrap_preformatted: bool) -> Self {
+        self.wrap_preformatted = wrap_preformatted;
+        self
+    }
+
+    /// Shows the preformatted lines in `highlighted` with syntax highlighting.
+    pub fn highlighted(mut self, highlighted: &'a HashMap<usize, HighlightedBlock>) -> Self {
+        self.highlighted = Some(highlighted);
+        self
+    }
+
+    /// Collapses preformatted blocks that have alt text behind a button
+    /// showing it, except for the lines in `expanded`.
+    pub fn collapse_preformatted(
+        mut self,
+        expanded: &'a HashSet<usize>,
+        on_toggle: fn(usize) -> Message,
+    ) -> Self {
+        self.collapse_preformatted = Some((expanded, on_toggle));
+        self
+    }
+
+    /// Highlights the lines added to the page and the lines removed from it,
+    /// given for each line of the document.
+    pub fn changes(mut self, changes: &'a [Change]) -> Self {
+        self.changes = Some(changes);
+        self
+    }
+
+    /// Highlights the matches of a search, and the `current` one of them
+    /// more strongly. `matches` are in the order of the lines they're on.
+    pub fn found(mut self, matches: &'a [Match], current: Option<usize>) -> Self {
+        self.found = Some((matches, current));
+        self
+    }
+
+    pub fn view(self) -> Element<'a, Message> {
+        Column::from_vec(
+            self.document
+                .lines
+                .iter()
+                .enumerate()
+                .map(|(i, line)| {
+                    let change = self.changes.and_then(|x| x.get(i));
+                    changed_line(self.line(i, line), change.copied())
+                })
+                .collect(),
+        )
+        .into()
+    }
+
+    /// Shows the image below links to it, if it's been loaded into `images`.
+    pub fn inline_images(mut self, inline_images: bool) -> Self {
+        self.inline_images = inline_images;
+        self
+    }
+
+    /// The matches on line `i`, and whether each is the current one.
+    fn found_in(&self, i: usize) -> Vec<(Range<usize>, bool)> {
+        let Some((matches, current)) = self.found else {
+            return Vec::new();
+        };
+        let start = matches.partition_point(|x| x.line < i);
+        matches[start..]
+            .iter()
+            .take_while(|x| x.line == i)
+            .enumerate()
+            .map(|(j, x)| (x.range.clone(), current == Some(start + j)))
+            .collect()
+    }
+
+    fn line(&self, i: usize, line: &'a Line) -> Element<'a, Message> {
+        let found = self.found_in(i);
+        if found.is_empty() {
+            return self.plain_line(i, line);
+        }
+        let element = match line {
+            Line::Text(s) => found_text("", s, &found, None),
+            Line::Heading(level, s) => {
+                let size = HEADING_SIZES[(*level).clamp(1, 3) - 1];
+                found_text("", s, &found, Some(size as f32))
+            }
+            Line::List(s) => found_text(" • ", s, &found, None),
+            Line::Quote(s) => Container::new(found_text("", s, &found, None))
+                .padding(padding::left(10))
+                .into(),
+            // shown by other widgets, so the whole line is highlighted
+            Line::Link(..) | Line::PreFormatted(..) => {
+                let current = found.iter().any(|(_, current)| *current);
+                Container::new(self.plain_line(i, line))
+                    .width(Fill)
+                    .style(move |_| {
+                        let color = if current { CURRENT_MATCH } else { MATCH };
+                        container::background(color.scale_alpha(0.4))
+                    })
+                    .into()
+            }
+            Line::Toggle(_) | Line::Image(_) => return self.plain_line(i, line),
+        };
+        // to be scrolled to
+        Container::new(element).id(line_id(i)).into()
+    }
+
+    fn plain_line(&self, i: usize, line: &'a Line) -> Element<'a, Message> {
+        match line {
+            Line::Text(s) => GeminiText::new(s).view(),
+            Line::Link(url, friendly) => {
+                let link =
+                    GeminiLink::new(url.clone(), friendly.clone(), self.on_press_link).view();
+                match self.images.get(url).filter(|_| self.inline_images) {
+                    Some(picture) => Column::new()
+                        .push(link)
+                        .push(
+                            Container::new(picture.view(ContentFit::ScaleDown))
+                                .max_height(MAX_PREVIEW_HEIGHT),
+                        )
+                        .into(),
+                    None => link,
+                }
+            }
+            Line::Heading(level, s) => Container::new(
+                GeminiText::new(s)
+                    .size(HEADING_SIZES[(*level).clamp(1, 3) - 1])
+                    .view(),
+            )
+            .id(line_id(i))
+            .into(),
+            Line::List(s) => GeminiText::new(&format!(" • {s}")).view(),
+            Line::Quote(s) => {
+                Element::new(Container::new(GeminiText::new(s).view()).padding(padding::left(10)))
+            }
+            Line::PreFormatted(s, alt) => self.preformatted(i, s, alt.as_deref()),
+            // the parser folds toggles into the preformatted block they open
+            Line::Toggle(_) => Column::new().into(),
+            Line::Image(_) => match self.images.get(&self.document.url) {
+                Some(picture) => picture.view(ContentFit::Contain),
+                None => GeminiText::new("(image not loaded)").view(),
+            },
+        }
+    }
+
+    fn preformatted(&self, i: usize, s: &'a str, alt: Option<&'a str>) -> Element<'a, Message> {
+        let highlighted = self.highlighted.and_then(|x| x.get(&i));
+        let wrap = self.wrap_preformatted;
+        let block = if self.shrink_preformatted {
+            let columns = if ansi::has_escapes(s) {
+                max_columns(&ansi::strip(s))
+            } else {
+                max_columns(s)
+            };
+            responsive(move |size| {
+                let size = fit_text_size(columns, size.width);
+                preformatted_block(s, highlighted, Some(size), wrap)
+            })
+            .height(Shrink)
+            .into()
+        } else {
+            preformatted_block(s, highlighted, None, wrap)
+        };
+        let Some(alt) = alt else {
+            return block;
+        };
+
+        let Some((expanded, on_toggle)) = self.collapse_preformatted else {
+            return GeminiTooltip::new(block, GeminiText::new(alt).view()).view();
+        };
+        let is_expanded = expanded.contains(&i);
+        let arrow = if is_expanded { "▼" } else { "▶" };
+        let toggle = button(GeminiText::new(&format!("{arrow} {alt}")).view())
+            .style(button::text)
+            .on_press(on_toggle(i));
+
+        if is_expanded {
+            Column::new().push(toggle).push(block).into()
+        } else {
+            toggle.into()
+        }
+    }
+}
+
+/// Tints `line` green if it was added, or red if it was removed.
+fn changed_line<'a, Message: 'a>(
+    line: Element<'a, Message>,
+    change: Option<Change>,
+) -> Element<'a, Message> {
+    let tint: fn(&Theme) -> Color = match change {
+        Some(Change::Added) => |theme: &Theme| theme.extended_palette().success.base.color,
+        Some(Change::Removed) => |theme: &Theme| theme.extended_palette().danger.base.color,
+        Some(Change::Unchanged) | None => return line,
+    };
+    Container::new(line)
+        .width(Fill)
+        .style(move |theme| container::background(tint(theme).scale_alpha(0.25)))
+        .into()
+}
+
+/// The ID of the container of line `i` in a document view. Only headings
+/// and lines with matches found are in one, for the outline and the find bar
+/// to scroll to.
+pub fn line_id(i: usize) -> Id {
+    Id::from(format!("line-{i}"))
+}
+
+/// Finds how far below the top of the container `content` each of `lines`
+/// in the document view in it was laid out. The offsets are the same
+/// however far the view has been scrolled, so can be scrolled to.
+pub fn line_offsets(content: Id, lines: &[usize]) -> Task<Vec<(usize, f32)>> {
+    widget::operate(LineOffsets {
+        content,
+        ids: lines.iter().map(|&i| (line_id(i), i)).collect(),
+        top: None,
+        found: Vec::new(),
+    })
+}
+
+struct LineOffsets {
+    content: Id,
+    ids: HashMap<Id, usize>, // line index by ID
+    top: Option<f32>,
+    found: Vec<(usize, f32)>, // line index, top of the line
+}
+
+impl Operation<Vec<(usize, f32)>> for LineOffsets {
+    fn traverse(&mut self, operate: &mut dyn FnMut(&mut dyn Operation<Vec<(usize, f32)>>)) {
+        operate(self);
+    }
+
+    fn container(&mut self, id: Option<&Id>, bounds: Rectangle) {
+        let Some(id) = id else {
+            return;
+        };
+        if *id == self.content {
+            self.top = Some(bounds.y);
+        } else if let Some(&line) = self.ids.get(id) {
+            self.found.push((line, bounds.y));
+        }
+    }
+
+    fn finish(&self) -> Outcome<Vec<(usize, f32)>> {
+        let top = self.top.unwrap_or_default();
+        let mut offsets: Vec<_> = self.found.iter().map(|&(i, y)| (i, y - top)).collect();
+        offsets.sort_by_key(|&(i, _)| i);
+        Outcome::Some(offsets)
+    }
+}
+
+const MATCH: Color = Color::from_rgb8(0xff, 0xe0, 0x66);
+const CURRENT_MATCH: Color = Color::from_rgb8(0xff, 0xa0, 0x30);
+
+/// `s` after `prefix`, with the ranges of it in `found` highlighted, and the
+/// current match more strongly.
+fn found_text<'a, Message: 'a>(
+    prefix: &'a str,
+    s: &'a str,
+    found: &[(Range<usize>, bool)],
+    size: Option<f32>,
+) -> Element<'a, Message> {
+    let mut spans: Vec<Span<'a, (), Font>> = vec![span(prefix)];
+    let mut end = 0;
+    for (range, current) in found {
+        spans.push(span(&s[end..range.start]));
+        spans.push(
+            span(&s[range.clone()])
+                .color(Color::BLACK)
+                .background(if *current { CURRENT_MATCH } else { MATCH }),
+        );
+        end = range.end;
+    }
+    spans.push(span(&s[end..]));
+    let mut text = rich_text(spans);
+    if let Some(size) = size {
+        text = text.size(size);
+    }
+    text.into()
+}
+
+const MAX_PREVIEW_HEIGHT: f32 = 400.0;
+const HEADING_SIZES: [u32; 3] = [40, 30, 20]; // by level
+const TEXT_SIZE: f32 = 16.0;
+const MIN_TEXT_SIZE: f32 = 8.0;
+// the advance of a monospace glyph, relative to the text size
+const MONOSPACE_ADVANCE: f32 = 0.6;
+
+/// A preformatted block on a horizontal scrollable, as its lines don't wrap
+/// unless `wrap` is set.
+fn preformatted_block<'a, Message: 'a>(
+    s: &'a str,
+    highlighted: Option<&'a HighlightedBlock>,
+    size: Option<f32>,
+    wrap: bool,
+) -> Element<'a, Message> {
+    let wrapping = if wrap {
+        Wrapping::WordOrGlyph
+    } else {
+        Wrapping::None
+    };
+    let text = match highlighted {
+        Some(highlighted) => highlighted.view(size, wrapping),
+        None => {
+            let mut text = GeminiText::new(s).font(Font::MONOSPACE).wrapping(wrapping);
+            if let Some(size) = size {
+                text = text.size(size);
+            }
+            text.view()
+        }
+    };
+    if wrap {
+        return text;
+    }
+    scrollable(text).horizontal().spacing(4).width(Fill).into()
+}
+
+fn max_columns(s: &str) -> usize {
+    s.lines().map(|x| x.chars().count()).max().unwrap_or(0)
+}
+
+/// The text size that fits `columns` monospace characters in `width`.
+fn fit_text_size(columns: usize, width: f32) -> f32 {
+    if columns == 0 {
+        return TEXT_SIZE;
+    }
+    (width / (columns as f32 * MONOSPACE_ADVANCE)).clamp(MIN_TEXT_SIZE, TEXT_SIZE)
+}
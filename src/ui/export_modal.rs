@@ -0,0 +1,47 @@
+use iced::{
+    Element,
+    Length::{Fill, Shrink},
+    widget::{Column, button, container},
+};
+
+use crate::{export::Format, ui::gemini_text::GeminiText};
+
+pub struct ExportModal<'a> {
+    status: Option<&'a str>,
+    busy: bool,
+}
+
+impl<'a> ExportModal<'a> {
+    /// `status` describes the last export, and `busy` disables exporting
+    /// while one is still being written.
+    pub fn new(status: Option<&'a str>, busy: bool) -> Self {
+        Self { status, busy }
+    }
+
+    pub fn view<Message: Clone + 'a>(
+        self,
+        on_export: fn(Format) -> Message,
+        on_close: Message,
+    ) -> Element<'a, Message> {
+        let options = Format::ALL
+            .into_iter()
+            .fold(Column::new().spacing(10), |column, format| {
+                column.push(
+                    button(GeminiText::new(format.label()).view())
+                        .width(Fill)
+                        .on_press_maybe((!self.busy).then(|| on_export(format))),
+                )
+            })
+            .push(self.status.map(|x| GeminiText::new(x).view()));
+
+        let button_row = container(button("Close").on_press(on_close))
+            .center_x(Fill)
+            .center_y(Shrink)
+            .padding(10);
+
+        Column::new()
+            .push(container(options).padding(10))
+            .push(button_row)
+            .into()
+    }
+}
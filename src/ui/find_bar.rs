@@ -0,0 +1,107 @@
+use iced::{
+    Alignment::Center,
+    Element,
+    advanced::widget::Id,
+    widget::{Row, button, checkbox, text_input},
+};
+use iced_gemini::gemini::find::Query;
+
+use crate::ui::gemini_text::GeminiText;
+
+/// The ID of the find bar's text input, for focusing it when it's opened.
+pub const FIND_INPUT: Id = Id::new("find");
+
+/// A bar for finding text on the page, showing which of the matches is the
+/// current one.
+pub struct FindBar<'a> {
+    query: &'a Query,
+    found: usize,
+    current: Option<usize>,
+    error: Option<&'a str>, // why the query couldn't be searched for
+}
+
+impl<'a> FindBar<'a> {
+    pub fn new(query: &'a Query, found: usize, current: Option<usize>) -> Self {
+        Self {
+            query,
+            found,
+            current,
+            error: None,
+        }
+    }
+
+    pub fn error(mut self, error: Option<&'a str>) -> Self {
+        self.error = error;
+        self
+    }
+
+    /// `on_change` receives the query as edited, and `on_next` is also sent
+    /// when the query is submitted.
+    pub fn view<Message: Clone + 'a>(
+        self,
+        on_change: fn(Query) -> Message,
+        on_next: Message,
+        on_previous: Message,
+        on_close: Message,
+    ) -> Element<'a, Message> {
+        let query = self.query;
+        let status = if let Some(error) = self.error {
+            error.to_string()
+        } else if query.text.is_empty() {
+            String::new()
+        } else if self.found == 0 {
+            "No matches".to_string()
+        } else {
+            format!("{} of {}", self.current.map_or(0, |x| x + 1), self.found)
+        };
+        let can_move = self.found > 0;
+
+        Row::new()
+            .spacing(10)
+            .padding(5)
+            .align_y(Center)
+            .push(
+                text_input("Find on page", &query.text)
+                    .id(FIND_INPUT)
+                    .width(300)
+                    .on_input(move |text| {
+                        on_change(Query {
+                            text,
+                            ..query.clone()
+                        })
+                    })
+                    .on_submit(on_next.clone()),
+            )
+            .push(GeminiText::new(&status).view())
+            .push(
+                button(GeminiText::new("▲").view()).on_press_maybe(can_move.then_some(on_previous)),
+            )
+            .push(button(GeminiText::new("▼").view()).on_press_maybe(can_move.then_some(on_next)))
+            .push(
+                checkbox(query.case_sensitive)
+                    .label("Match case")
+                    .on_toggle(move |case_sensitive| {
+                        on_change(Query {
+                            case_sensitive,
+                            ..query.clone()
+                        })
+                    }),
+            )
+            .push(
+                checkbox(query.regex)
+                    .label("Regex")
+                    .on_toggle(move |regex| {
+                        on_change(Query {
+                            regex,
+                            ..query.clone()
+                        })
+                    }),
+            )
+            .push(
+                button(GeminiText::new("✕").view())
+                    .style(button::text)
+                    .on_press(on_close),
+            )
+            .into()
+    }
+}
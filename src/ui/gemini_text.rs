@@ -1,9 +1,13 @@
-use iced::{Element, Font, Pixels, widget::text};
+use iced::{
+    Element, Font, Pixels,
+    widget::{text, text::Wrapping},
+};
 
 pub struct GeminiText {
     text: String,
     size: Option<Pixels>,
     font: Option<Font>,
+    wrapping: Option<Wrapping>,
 }
 
 impl GeminiText {
@@ -12,6 +16,7 @@ impl GeminiText {
             text: text.to_string(),
             size: None,
             font: None,
+            wrapping: None,
         }
     }
 
@@ -25,6 +30,11 @@ impl GeminiText {
         self
     }
 
+    pub fn wrapping(mut self, wrapping: Wrapping) -> Self {
+        self.wrapping = Some(wrapping);
+        self
+    }
+
     pub fn view<'a, Message>(self) -> Element<'a, Message> {
         let mut t = text(self.text).shaping(text::Shaping::Advanced);
         if let Some(size) = self.size {
@@ -35,6 +45,10 @@ impl GeminiText {
             t = t.font(font);
         }
 
+        if let Some(wrapping) = self.wrapping {
+            t = t.wrapping(wrapping);
+        }
+
         t.into()
     }
 }
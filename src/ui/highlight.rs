@@ -0,0 +1,162 @@
+use iced::{
+    Color, Element, Font,
+    font::Weight,
+    widget::{
+        rich_text, span,
+        text::{Span, Wrapping},
+    },
+};
+use iced_gemini::gemini::ansi::{self, AnsiColor};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Theme, ThemeSet},
+    parsing::{SyntaxReference, SyntaxSet},
+    util::LinesWithEndings,
+};
+
+const THEME: &str = "InspiredGitHub";
+
+/// Syntax highlighting for preformatted blocks whose alt text names a
+/// language, e.g. ```` ```rust ```` or the `text/x-python` a plain text
+/// response is labelled with.
+pub struct Highlighter {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+}
+
+impl Highlighter {
+    pub fn new() -> Self {
+        let mut themes = ThemeSet::load_defaults();
+        Self {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme: themes
+                .themes
+                .remove(THEME)
+                .expect("Highlighter: missing built-in theme"),
+        }
+    }
+
+    /// The language named by the first word of `alt`, either directly
+    /// (`rust`, `py`) or as a MIME type (`text/x-rust`).
+    fn syntax(&self, alt: &str) -> Option<&SyntaxReference> {
+        let word = alt.split_whitespace().next()?.to_ascii_lowercase();
+        let token = word
+            .strip_prefix("text/x-")
+            .or_else(|| word.strip_prefix("text/"))
+            .unwrap_or(&word);
+        self.syntaxes.find_syntax_by_token(token)
+    }
+
+    /// Returns `None` if `alt` doesn't name a known language.
+    pub fn highlight(&self, alt: &str, code: &str) -> Option<HighlightedBlock> {
+        let syntax = self.syntax(alt)?;
+        let mut lines = HighlightLines::new(syntax, &self.theme);
+
+        let mut spans: Vec<StyledSpan> = Vec::new();
+        for line in LinesWithEndings::from(code) {
+            let ranges = match lines.highlight_line(line, &self.syntaxes) {
+                Ok(x) => x,
+                Err(e) => {
+                    log::warn!("Highlighter: failed to highlight as {}: {e}", syntax.name);
+                    return None;
+                }
+            };
+
+            for (style, s) in ranges {
+                let c = style.foreground;
+                let color = Color::from_rgba8(c.r, c.g, c.b, f32::from(c.a) / 255.0);
+                // merge runs of the same colour to keep the number of spans down
+                match spans.last_mut() {
+                    Some(last) if last.color == Some(color) => last.text.push_str(s),
+                    _ => spans.push(StyledSpan {
+                        text: s.to_string(),
+                        color: Some(color),
+                        ..StyledSpan::default()
+                    }),
+                }
+            }
+        }
+        Some(HighlightedBlock { spans })
+    }
+}
+
+impl Default for Highlighter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct StyledSpan {
+    text: String,
+    color: Option<Color>,
+    background: Option<Color>,
+    bold: bool,
+    underline: bool,
+}
+
+/// A preformatted block split into styled runs.
+#[derive(Debug, Clone)]
+pub struct HighlightedBlock {
+    spans: Vec<StyledSpan>,
+}
+
+impl HighlightedBlock {
+    /// Styles `s` according to the ANSI SGR sequences in it.
+    pub fn from_ansi(s: &str) -> Self {
+        let color = |x: AnsiColor| {
+            let (r, g, b) = x.to_rgb();
+            Color::from_rgb8(r, g, b)
+        };
+        let spans = ansi::parse(s)
+            .into_iter()
+            .map(|x| StyledSpan {
+                text: x.text,
+                color: x.style.foreground.map(color),
+                background: x.style.background.map(color),
+                bold: x.style.bold,
+                underline: x.style.underline,
+            })
+            .collect();
+        Self { spans }
+    }
+
+    /// `s` without any styling.
+    pub fn plain(s: String) -> Self {
+        Self {
+            spans: vec![StyledSpan {
+                text: s,
+                ..StyledSpan::default()
+            }],
+        }
+    }
+
+    /// `size` overrides the default text size, and `wrapping` is how lines
+    /// too long for the width are wrapped, if at all.
+    pub fn view<'a, Message: 'a>(
+        &'a self,
+        size: Option<f32>,
+        wrapping: Wrapping,
+    ) -> Element<'a, Message> {
+        let spans: Vec<Span<'a, (), Font>> = self
+            .spans
+            .iter()
+            .map(|x| {
+                let weight = if x.bold { Weight::Bold } else { Weight::Normal };
+                span(x.text.as_str())
+                    .color_maybe(x.color)
+                    .background_maybe(x.background)
+                    .underline(x.underline)
+                    .font(Font {
+                        weight,
+                        ..Font::MONOSPACE
+                    })
+            })
+            .collect();
+        let mut text = rich_text(spans).font(Font::MONOSPACE).wrapping(wrapping);
+        if let Some(size) = size {
+            text = text.size(size);
+        }
+        text.into()
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::{HashMap, VecDeque};
+
+use url::Url;
+
+use crate::ui::picture::Picture;
+
+// the oldest pictures are dropped past this many bytes
+const MAX_BYTES: usize = 256 * 1024 * 1024;
+
+/// Decoded pictures, keyed by the URL they were loaded from. Past a budget
+/// of bytes, the pictures inserted longest ago are dropped, though never the
+/// last one inserted.
+#[derive(Debug)]
+pub struct ImageCache {
+    pictures: HashMap<Url, Picture>,
+    order: VecDeque<Url>, // oldest first
+    bytes: usize,
+    max_bytes: usize,
+}
+
+impl ImageCache {
+    pub fn new() -> Self {
+        Self::with_max_bytes(MAX_BYTES)
+    }
+
+    fn with_max_bytes(max_bytes: usize) -> Self {
+        Self {
+            pictures: HashMap::new(),
+            order: VecDeque::new(),
+            bytes: 0,
+            max_bytes,
+        }
+    }
+
+    pub fn get(&self, url: &Url) -> Option<&Picture> {
+        self.pictures.get(url)
+    }
+
+    pub fn contains(&self, url: &Url) -> bool {
+        self.pictures.contains_key(url)
+    }
+
+    pub fn pictures(&self) -> impl Iterator<Item = &Picture> {
+        self.pictures.values()
+    }
+
+    pub fn pictures_mut(&mut self) -> impl Iterator<Item = &mut Picture> {
+        self.pictures.values_mut()
+    }
+
+    /// Replaces any picture already loaded from `url`, then drops the oldest
+    /// pictures until the rest fit in the budget.
+    pub fn insert(&mut self, url: Url, picture: Picture) {
+        self.remove(&url);
+        self.bytes += picture.size();
+        self.pictures.insert(url.clone(), picture);
+        self.order.push_back(url);
+        while self.bytes > self.max_bytes && self.order.len() > 1 {
+            if let Some(oldest) = self.order.front().cloned() {
+                self.remove(&oldest);
+            }
+        }
+    }
+
+    /// Keeps only the pictures loaded from URLs `keep` returns `true` for.
+    pub fn retain(&mut self, mut keep: impl FnMut(&Url) -> bool) {
+        let removed: Vec<Url> = self.order.iter().filter(|x| !keep(x)).cloned().collect();
+        for url in &removed {
+            self.remove(url);
+        }
+    }
+
+    fn remove(&mut self, url: &Url) {
+        if let Some(picture) = self.pictures.remove(url) {
+            self.bytes -= picture.size();
+            self.order.retain(|x| x != url);
+        }
+    }
+}
+
+impl Default for ImageCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iced::advanced::image::Handle;
+
+    use super::*;
+
+    fn picture(size: usize) -> Picture {
+        Picture::Raster(Handle::from_bytes(vec![0; size]))
+    }
+
+    #[test]
+    fn test_drops_oldest() -> anyhow::Result<()> {
+        let url = |x| Url::parse(&format!("gemini://example.com/{x}.png"));
+        let mut cache = ImageCache::with_max_bytes(10);
+        cache.insert(url(1)?, picture(4));
+        cache.insert(url(2)?, picture(4));
+        cache.insert(url(1)?, picture(4));
+        assert_eq!(cache.bytes, 8);
+
+        cache.insert(url(3)?, picture(4));
+        assert!(!cache.contains(&url(2)?));
+        assert!(cache.contains(&url(1)?) && cache.contains(&url(3)?));
+
+        // a picture over the budget on its own is still kept
+        cache.insert(url(4)?, picture(20));
+        assert_eq!(cache.pictures().count(), 1);
+        assert!(cache.contains(&url(4)?));
+
+        let last = url(4)?;
+        cache.retain(|x| *x != last);
+        assert_eq!((cache.pictures().count(), cache.bytes), (0, 0));
+        Ok(())
+    }
+}
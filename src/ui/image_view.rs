@@ -0,0 +1,261 @@
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use iced::{
+    ContentFit, Element,
+    Length::Fill,
+    widget::{Column, Row, button, container, image::viewer},
+};
+use url::Url;
+
+use crate::{
+    config,
+    ui::{
+        gemini_text::GeminiText,
+        picture::{self, Picture},
+    },
+};
+
+const MIN_SCALE: f32 = 0.1;
+const MAX_SCALE: f32 = 20.0;
+const SCALE_STEP: f32 = 0.1;
+
+/// How an image is initially sized in the viewer, before any zooming.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImageFit {
+    /// Scaled down to fit the window.
+    #[default]
+    Window,
+    /// At its natural size.
+    Actual,
+}
+
+impl From<ImageFit> for ContentFit {
+    fn from(fit: ImageFit) -> Self {
+        match fit {
+            ImageFit::Window => ContentFit::ScaleDown,
+            ImageFit::Actual => ContentFit::None,
+        }
+    }
+}
+
+/// What can be read from an image's header without decoding it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageInfo {
+    pub format: Option<String>,
+    pub extension: Option<&'static str>, // the one files in this format usually have
+    pub dimensions: Option<(u32, u32)>,
+    pub size: usize, // in bytes
+}
+
+impl ImageInfo {
+    pub fn read(data: &[u8]) -> Self {
+        if picture::is_svg(data) {
+            return Self {
+                format: Some("SVG".to_string()),
+                extension: Some("svg"),
+                dimensions: None,
+                size: data.len(),
+            };
+        }
+        let reader = image::ImageReader::new(Cursor::new(data)).with_guessed_format();
+        let format = reader.as_ref().ok().and_then(image::ImageReader::format);
+        let dimensions = reader.ok().and_then(|x| x.into_dimensions().ok());
+        Self {
+            format: format.map(|x| format!("{x:?}").to_uppercase()),
+            extension: format.and_then(|x| x.extensions_str().first().copied()),
+            dimensions,
+            size: data.len(),
+        }
+    }
+}
+
+impl std::fmt::Display for ImageInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some((width, height)) = self.dimensions {
+            write!(f, "{width} × {height} · ")?;
+        }
+        match &self.format {
+            Some(format) => write!(f, "{format} · ")?,
+            None => write!(f, "unknown format · ")?,
+        }
+        write!(f, "{}", format_size(self.size))
+    }
+}
+
+fn format_size(size: usize) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+    if size < 1024 {
+        return format!("{size} B");
+    }
+    let mut size = size as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Writes `data` into `dir`, named after the last segment of `url`, or given
+/// the extension of `info`'s format if that has none. Never overwrites an
+/// existing file.
+pub fn save(data: &[u8], info: &ImageInfo, url: &Url, dir: &Path) -> anyhow::Result<PathBuf> {
+    let name = url
+        .path_segments()
+        .and_then(|mut x| x.next_back())
+        .filter(|x| !x.is_empty())
+        .unwrap_or("image");
+    let name = Path::new(name);
+    let stem = name
+        .file_stem()
+        .unwrap_or(name.as_os_str())
+        .to_string_lossy();
+    let extension = match name.extension() {
+        Some(x) => Some(x.to_string_lossy().into_owned()),
+        None => info.extension.map(str::to_string),
+    };
+
+    Ok(config::write_new(dir, &stem, extension.as_deref(), data)?)
+}
+
+/// An image response shown on its own, which can be zoomed with the mouse
+/// wheel and panned by dragging.
+pub struct ImageView<'a> {
+    picture: &'a Picture,
+    info: &'a ImageInfo,
+    fit: ImageFit,
+    status: Option<&'a str>,
+}
+
+impl<'a> ImageView<'a> {
+    /// `picture` is what's shown, which may be a rotated copy of the image
+    /// `info` was read from.
+    pub fn new(info: &'a ImageInfo, picture: &'a Picture) -> Self {
+        Self {
+            picture,
+            info,
+            fit: ImageFit::default(),
+            status: None,
+        }
+    }
+
+    pub fn fit(mut self, fit: ImageFit) -> Self {
+        self.fit = fit;
+        self
+    }
+
+    /// A note shown after the image's metadata, e.g. where it was saved.
+    pub fn status(mut self, status: Option<&'a str>) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn view<Message: Clone + 'a>(
+        self,
+        on_fit: fn(ImageFit) -> Message,
+        on_rotate: Message,
+        on_save: Message,
+    ) -> Element<'a, Message> {
+        let fit_button = |label: &str, fit: ImageFit| {
+            button(GeminiText::new(label).view())
+                .style(if self.fit == fit {
+                    button::primary
+                } else {
+                    button::secondary
+                })
+                .on_press(on_fit(fit))
+        };
+        let mut details = self.info.to_string();
+        if let Some(status) = self.status {
+            details.push_str(" · ");
+            details.push_str(status);
+        }
+
+        let controls = Row::new()
+            .push(fit_button("Fit to window", ImageFit::Window))
+            .push(fit_button("Actual size", ImageFit::Actual))
+            .push(button(GeminiText::new("⟳ Rotate").view()).on_press(on_rotate))
+            .push(button(GeminiText::new("Save").view()).on_press(on_save))
+            .push(GeminiText::new(&details).view())
+            .spacing(10)
+            .align_y(iced::Center);
+
+        // the viewer only takes raster images, so SVGs can't be zoomed
+        let image: Element<'a, Message> = match self.picture.raster() {
+            Some(handle) => viewer(handle.clone())
+                .content_fit(self.fit.into())
+                .min_scale(MIN_SCALE)
+                .max_scale(MAX_SCALE)
+                .scale_step(SCALE_STEP)
+                .width(Fill)
+                .height(Fill)
+                .into(),
+            None => container(self.picture.view(self.fit.into()))
+                .center(Fill)
+                .into(),
+        };
+
+        Column::new()
+            .push(controls)
+            .push(image)
+            .spacing(10)
+            .padding(10)
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MiB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024 * 1024), "3072.0 GiB");
+    }
+
+    #[test]
+    fn test_save() -> anyhow::Result<()> {
+        let dir =
+            std::env::temp_dir().join(format!("iced_gemini-test-save-{}", std::process::id()));
+        let png = b"\x89PNG\r\n\x1a\n";
+        let info = ImageInfo::read(png);
+        assert_eq!(info.extension, Some("png"));
+
+        let saved = [
+            save(
+                png,
+                &info,
+                &Url::parse("gemini://example.com/cat.jpg")?,
+                &dir,
+            )?,
+            save(
+                png,
+                &info,
+                &Url::parse("gemini://example.com/a/cat.jpg")?,
+                &dir,
+            )?,
+            save(png, &info, &Url::parse("gemini://example.com/cat")?, &dir)?,
+            save(png, &info, &Url::parse("gemini://example.com/")?, &dir)?,
+        ];
+        let names: Vec<_> = saved
+            .iter()
+            .map(|x| {
+                x.strip_prefix(&dir)
+                    .map(|x| x.to_string_lossy().into_owned())
+            })
+            .collect::<Result<_, _>>()?;
+        let contents = std::fs::read(&saved[0])?;
+        std::fs::remove_dir_all(&dir)?;
+
+        assert_eq!(names, ["cat.jpg", "cat (1).jpg", "cat.png", "image.png"]);
+        assert_eq!(contents, png);
+        Ok(())
+    }
+}
@@ -1,6 +1,17 @@
+pub mod confirm_modal;
+pub mod document_view;
 pub mod error_dialog;
+pub mod export_modal;
+pub mod find_bar;
 pub mod gemini_link;
 pub mod gemini_text;
 pub mod gemini_tooltip;
-pub mod modal;
+pub mod highlight;
+pub mod image_cache;
+pub mod image_view;
 pub mod input_modal;
+pub mod modal;
+pub mod outline;
+pub mod picture;
+pub mod render;
+pub mod settings_modal;
@@ -0,0 +1,140 @@
+use iced::{
+    Element,
+    Length::Fill,
+    padding,
+    widget::{Column, button, container, scrollable},
+};
+use iced_gemini::gemini::gemtext::{Document, Line};
+
+use crate::ui::gemini_text::GeminiText;
+
+const WIDTH: f32 = 250.0;
+const INDENT: f32 = 12.0;
+
+/// A heading in the outline of a document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading<'a> {
+    pub line: usize,  // index in the document
+    pub depth: usize, // how many headings it's nested under
+    pub text: &'a str,
+}
+
+/// The headings of `doc`, each nested under the closest heading before it
+/// of a higher level.
+pub fn headings(doc: &Document) -> Vec<Heading<'_>> {
+    let mut open: Vec<usize> = Vec::new(); // the levels of the headings we're under
+    let mut headings = Vec::new();
+    for (line, x) in doc.lines.iter().enumerate() {
+        let Line::Heading(level, text) = x else {
+            continue;
+        };
+        while open.last().is_some_and(|x| x >= level) {
+            open.pop();
+        }
+        headings.push(Heading {
+            line,
+            depth: open.len(),
+            text: text.trim(),
+        });
+        open.push(*level);
+    }
+    headings
+}
+
+/// A sidebar listing a document's headings, highlighting the `current` one.
+pub struct Outline<'a> {
+    headings: Vec<Heading<'a>>,
+    current: Option<usize>, // line index
+}
+
+impl<'a> Outline<'a> {
+    pub fn new(doc: &'a Document, current: Option<usize>) -> Self {
+        Self {
+            headings: headings(doc),
+            current,
+        }
+    }
+
+    /// `on_press` receives the line index of the heading pressed.
+    pub fn view<Message: Clone + 'a>(self, on_press: fn(usize) -> Message) -> Element<'a, Message> {
+        let entries = if self.headings.is_empty() {
+            Column::new().push(GeminiText::new("No headings").view())
+        } else {
+            Column::from_vec(
+                self.headings
+                    .iter()
+                    .map(|heading| {
+                        let current = self.current == Some(heading.line);
+                        button(GeminiText::new(heading.text).size(14).view())
+                            .width(Fill)
+                            .padding(padding::all(4).left(4.0 + INDENT * heading.depth as f32))
+                            .style(if current {
+                                button::primary
+                            } else {
+                                button::text
+                            })
+                            .on_press(on_press(heading.line))
+                            .into()
+                    })
+                    .collect(),
+            )
+        };
+        container(scrollable(entries.spacing(2).padding(10)))
+            .width(WIDTH)
+            .height(Fill)
+            .into()
+    }
+}
+
+/// The heading the view scrolled to `scrolled` is in: the last one above the
+/// top of the view, with a little leeway for one just scrolled to.
+pub fn current_heading(offsets: &[(usize, f32)], scrolled: f32) -> Option<usize> {
+    const LEEWAY: f32 = 40.0;
+    offsets
+        .iter()
+        .take_while(|(_, top)| *top <= scrolled + LEEWAY)
+        .last()
+        .map(|&(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::*;
+
+    #[test]
+    fn test_headings() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/")?;
+        let doc = Document::parse(
+            &url,
+            "## Intro\ntext\n# Title\n## Part one\n### Detail\n## Part two\n### More\n",
+        );
+        let depths: Vec<_> = headings(&doc)
+            .iter()
+            .map(|x| (x.line, x.depth, x.text))
+            .collect();
+        assert_eq!(
+            depths,
+            [
+                (0, 0, "Intro"),
+                (2, 0, "Title"),
+                (3, 1, "Part one"),
+                (4, 2, "Detail"),
+                (5, 1, "Part two"),
+                (6, 2, "More"),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_current_heading() {
+        let offsets = [(0, 0.0), (4, 300.0), (9, 900.0)];
+        assert_eq!(current_heading(&offsets, 0.0), Some(0));
+        assert_eq!(current_heading(&offsets, 299.0), Some(4));
+        assert_eq!(current_heading(&offsets, 500.0), Some(4));
+        assert_eq!(current_heading(&offsets, 5000.0), Some(9));
+        assert_eq!(current_heading(&[(3, 100.0)], 0.0), None);
+    }
+}
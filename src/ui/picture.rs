@@ -0,0 +1,302 @@
+use std::{f32::consts::FRAC_PI_2, io::Cursor};
+
+use ::image::{
+    AnimationDecoder, ImageDecoder, ImageFormat, ImageReader, Limits, RgbaImage,
+    codecs::{gif::GifDecoder, png::PngDecoder},
+    guess_format, imageops,
+};
+use iced::{
+    ContentFit, Element, Radians, Rotation,
+    advanced::{image::Handle, svg::Data},
+    time::{Duration, Instant},
+    widget::{image, svg},
+};
+
+// frames past this many bytes of pixels are dropped, and images we decode
+// ourselves may not take up more
+const MAX_ANIMATION_BYTES: usize = 256 * 1024 * 1024;
+// images wider or taller than this aren't decoded here
+const MAX_ANIMATION_SIDE: u32 = 8192;
+// browsers treat shorter delays as a mistake and slow them down to this
+const MIN_FRAME_DELAY: Duration = Duration::from_millis(20);
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+
+/// Whether `data` looks like an SVG document.
+pub fn is_svg(data: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(&data[..data.len().min(1024)]);
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    head.starts_with("<svg")
+        || ((head.starts_with("<?xml") || head.starts_with("<!")) && head.contains("<svg"))
+}
+
+/// A decoded image, ready to be drawn.
+#[derive(Debug, Clone)]
+pub enum Picture {
+    Raster(Handle),
+    Svg(svg::Handle, u8), // quarter turns clockwise
+    Animated(Animation),
+}
+
+impl Picture {
+    /// Falls back to letting the renderer decode `data` if it can't be
+    /// decoded here.
+    pub fn new(data: &[u8]) -> Self {
+        Self::decode(data, 0).unwrap_or_else(|e| {
+            log::warn!("Picture: failed to decode: {e}");
+            Picture::Raster(Handle::from_bytes(data.to_vec()))
+        })
+    }
+
+    /// Decodes `data`, turned clockwise by `quarter_turns` right angles.
+    pub fn decode(data: &[u8], quarter_turns: u8) -> anyhow::Result<Self> {
+        let quarter_turns = quarter_turns % 4;
+        if is_svg(data) {
+            return Ok(Picture::Svg(
+                svg::Handle::from_memory(data.to_vec()),
+                quarter_turns,
+            ));
+        }
+        if let Some(animation) = Animation::decode(data, quarter_turns)? {
+            return Ok(Picture::Animated(animation));
+        }
+        if quarter_turns == 0 {
+            // left to the renderer, which decodes it when it's first drawn
+            return Ok(Picture::Raster(Handle::from_bytes(data.to_vec())));
+        }
+        let mut reader = ImageReader::new(Cursor::new(data)).with_guessed_format()?;
+        reader.limits(limits());
+        let image = rotate(reader.decode()?.into_rgba8(), quarter_turns);
+        Ok(Picture::Raster(Handle::from_rgba(
+            image.width(),
+            image.height(),
+            image.into_raw(),
+        )))
+    }
+
+    /// Roughly how many bytes of memory the picture's data takes up.
+    pub fn size(&self) -> usize {
+        match self {
+            Picture::Raster(x) => handle_size(x),
+            Picture::Svg(x, _) => match x.data() {
+                Data::Bytes(x) => x.len(),
+                Data::Path(_) => 0,
+            },
+            Picture::Animated(x) => x.frames.iter().map(|(x, _)| handle_size(x)).sum(),
+        }
+    }
+
+    pub fn animation_mut(&mut self) -> Option<&mut Animation> {
+        match self {
+            Picture::Animated(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    /// The raster image to draw, which for animations is the current frame.
+    pub fn raster(&self) -> Option<&Handle> {
+        match self {
+            Picture::Raster(x) => Some(x),
+            Picture::Animated(x) => Some(x.frame()),
+            Picture::Svg(..) => None,
+        }
+    }
+
+    pub fn view<'a, Message: 'a>(&self, content_fit: ContentFit) -> Element<'a, Message> {
+        match self {
+            Picture::Svg(handle, quarter_turns) => svg(handle.clone())
+                .content_fit(content_fit)
+                .rotation(Rotation::Solid(Radians(
+                    FRAC_PI_2 * f32::from(*quarter_turns),
+                )))
+                .into(),
+            Picture::Raster(handle) => image(handle).content_fit(content_fit).into(),
+            Picture::Animated(x) => image(x.frame()).content_fit(content_fit).into(),
+        }
+    }
+}
+
+fn handle_size(handle: &Handle) -> usize {
+    match handle {
+        Handle::Bytes(_, bytes) => bytes.len(),
+        Handle::Rgba { pixels, .. } => pixels.len(),
+        Handle::Path(..) => 0,
+    }
+}
+
+fn rotate(image: RgbaImage, quarter_turns: u8) -> RgbaImage {
+    match quarter_turns % 4 {
+        0 => image,
+        1 => imageops::rotate90(&image),
+        2 => imageops::rotate180(&image),
+        _ => imageops::rotate270(&image),
+    }
+}
+
+/// How large an image we decode ourselves may be.
+fn limits() -> Limits {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_ANIMATION_SIDE);
+    limits.max_image_height = Some(MAX_ANIMATION_SIDE);
+    limits.max_alloc = Some(MAX_ANIMATION_BYTES as u64);
+    limits
+}
+
+/// The frames of an animated GIF or PNG, played back by `tick`.
+#[derive(Debug, Clone)]
+pub struct Animation {
+    frames: Vec<(Handle, Duration)>,
+    current: usize,
+    shown_at: Option<Instant>, // when the current frame was first drawn
+}
+
+impl Animation {
+    /// Returns `None` if `data` isn't a GIF or PNG with more than one frame.
+    fn decode(data: &[u8], quarter_turns: u8) -> anyhow::Result<Option<Self>> {
+        let limits = limits();
+        // too large to animate, so it's left to be shown still
+        let too_large = |e| {
+            log::warn!("Animation: not animating: {e}");
+            Ok(None)
+        };
+        let format = guess_format(data).ok();
+        let ((width, height), mut frames) = match format {
+            Some(ImageFormat::Gif) => {
+                let mut decoder = GifDecoder::new(Cursor::new(data))?;
+                if let Err(e) = decoder.set_limits(limits) {
+                    return too_large(e);
+                }
+                (decoder.dimensions(), decoder.into_frames())
+            }
+            Some(ImageFormat::Png) => {
+                let mut decoder = PngDecoder::new(Cursor::new(data))?;
+                if !decoder.is_apng()? {
+                    return Ok(None);
+                }
+                if let Err(e) = decoder.set_limits(limits) {
+                    return too_large(e);
+                }
+                (decoder.dimensions(), decoder.apng()?.into_frames())
+            }
+            _ => return Ok(None),
+        };
+
+        // every frame is decoded onto a canvas the size of the whole image
+        let frame_size = width as usize * height as usize * 4;
+        let mut decoded = Vec::new();
+        let mut total = 0;
+        loop {
+            if total + frame_size > MAX_ANIMATION_BYTES {
+                log::warn!("Animation: dropping any frames past {}", decoded.len());
+                break;
+            }
+            let Some(frame) = frames.next() else {
+                break;
+            };
+            let frame = frame?;
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            let delay =
+                Duration::from_secs_f64(f64::from(numer) / f64::from(denom.max(1)) / 1000.0);
+            let delay = if delay < MIN_FRAME_DELAY {
+                DEFAULT_FRAME_DELAY
+            } else {
+                delay
+            };
+            let image = rotate(frame.into_buffer(), quarter_turns);
+
+            total += frame_size;
+            let handle = Handle::from_rgba(image.width(), image.height(), image.into_raw());
+            decoded.push((handle, delay));
+        }
+
+        if decoded.len() < 2 {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            frames: decoded,
+            current: 0,
+            shown_at: None,
+        }))
+    }
+
+    pub fn frame(&self) -> &Handle {
+        &self.frames[self.current].0
+    }
+
+    /// Moves on to the frame that should be showing at `now`.
+    pub fn tick(&mut self, now: Instant) {
+        let Some(shown_at) = self.shown_at else {
+            self.shown_at = Some(now);
+            return;
+        };
+        let delay = self.frames[self.current].1;
+        if now.duration_since(shown_at) >= delay {
+            self.current = (self.current + 1) % self.frames.len();
+            // don't try to catch up after a stall, just carry on from here
+            self.shown_at = Some(now);
+        }
+    }
+
+    /// Stops the clock, so playback resumes from the current frame.
+    pub fn pause(&mut self) {
+        self.shown_at = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::image::{Delay, Frame, codecs::gif::GifEncoder};
+
+    use super::*;
+
+    fn gif(width: u32, height: u32, frames: usize) -> anyhow::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        let mut encoder = GifEncoder::new(&mut data);
+        for _ in 0..frames {
+            let delay = Delay::from_numer_denom_ms(50, 1);
+            encoder.encode_frame(Frame::from_parts(
+                RgbaImage::new(width, height),
+                0,
+                0,
+                delay,
+            ))?;
+        }
+        drop(encoder);
+        Ok(data)
+    }
+
+    #[test]
+    fn test_decode_animation() -> anyhow::Result<()> {
+        let animation = Animation::decode(&gif(4, 2, 3)?, 1)?.unwrap();
+        assert_eq!(animation.frames.len(), 3);
+        assert_eq!(animation.frames[0].1, Duration::from_millis(50));
+        assert!(matches!(
+            animation.frame(),
+            Handle::Rgba {
+                width: 2,
+                height: 4,
+                ..
+            }
+        ));
+
+        assert!(Animation::decode(&gif(4, 2, 1)?, 0)?.is_none());
+        // left to be shown still rather than decoded
+        assert!(Animation::decode(&gif(MAX_ANIMATION_SIDE + 1, 1, 2)?, 0)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_rotated() -> anyhow::Result<()> {
+        let picture = Picture::decode(&gif(4, 2, 1)?, 3)?;
+        assert!(matches!(
+            picture,
+            Picture::Raster(Handle::Rgba {
+                width: 2,
+                height: 4,
+                ..
+            })
+        ));
+        // held to the same limits as animations
+        assert!(Picture::decode(&gif(MAX_ANIMATION_SIDE + 1, 1, 1)?, 1).is_err());
+        Ok(())
+    }
+}
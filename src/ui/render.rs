@@ -0,0 +1,129 @@
+//! Draws pages offscreen with the software renderer, with no window and no
+//! GPU: for previews of pages, and for comparing how lines look in tests.
+
+use iced::{
+    Element, Font, Pixels, Rectangle, Size, Theme,
+    advanced::{
+        Layout, layout, mouse,
+        renderer::{self, Headless},
+        widget::Tree,
+    },
+    executor::{self, Executor},
+    widget::container,
+};
+use iced_gemini::gemini::gemtext::Document;
+use image::RgbaImage;
+
+use crate::ui::{document_view::DocumentView, image_cache::ImageCache};
+
+/// The tallest image drawn, in pixels. Longer pages are cut off.
+pub const MAX_HEIGHT: u32 = 32_000;
+
+/// Draws `doc` as it's shown in the window, `width` pixels wide and as tall
+/// as it needs to be. Links can't be pressed, and as an image can't be
+/// scrolled, preformatted lines too wide for it are wrapped.
+pub fn render_document(doc: &Document, width: u32, theme: &Theme) -> anyhow::Result<RgbaImage> {
+    let images = ImageCache::new();
+    let view = DocumentView::new(doc, &images, |_| ())
+        .wrap_preformatted(true)
+        .view();
+    render(container(view).padding(20).into(), width, theme)
+}
+
+/// Lays out `element` `width` pixels wide and draws it with tiny-skia.
+pub fn render<Message>(
+    mut element: Element<'_, Message>,
+    width: u32,
+    theme: &Theme,
+) -> anyhow::Result<RgbaImage> {
+    let mut renderer = executor::Default::new()?
+        .block_on(<iced::Renderer as Headless>::new(
+            Font::with_name("Arial"),
+            Pixels(16.0),
+            Some("tiny-skia"),
+        ))
+        .ok_or(anyhow::anyhow!("the software renderer isn't available"))?;
+
+    let mut tree = Tree::new(element.as_widget());
+    let limits = layout::Limits::new(Size::ZERO, Size::new(width as f32, f32::INFINITY));
+    let node = element
+        .as_widget_mut()
+        .layout(&mut tree, &renderer, &limits);
+
+    let height = node.size().height.ceil() as u32;
+    if height > MAX_HEIGHT {
+        log::warn!("render: cutting the page off at {MAX_HEIGHT} of {height} pixels");
+    }
+    let height = height.clamp(1, MAX_HEIGHT);
+    let size = Size::new(width, height);
+
+    let palette = theme.palette();
+    element.as_widget().draw(
+        &tree,
+        &mut renderer,
+        theme,
+        &renderer::Style {
+            text_color: palette.text,
+        },
+        Layout::new(&node),
+        mouse::Cursor::Unavailable,
+        &Rectangle::with_size(Size::new(width as f32, height as f32)),
+    );
+    let pixels = renderer.screenshot(size, 1.0, palette.background);
+    RgbaImage::from_raw(width, height, pixels).ok_or(anyhow::anyhow!(
+        "the renderer drew the wrong number of pixels"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::*;
+
+    #[test]
+    fn test_render_document() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/")?;
+        let short = Document::parse(&url, "# Hello\n");
+        let long = Document::parse(&url, "# Hello\nSome text\n=> /a A link\n* a list\n");
+
+        let theme = Theme::Light;
+        let image = render_document(&short, 320, &theme)?;
+        assert_eq!(image.width(), 320);
+        // the heading is drawn over the background
+        let background = image.get_pixel(0, 0);
+        assert!(image.pixels().any(|x| x != background));
+
+        let taller = render_document(&long, 320, &theme)?;
+        assert!(taller.height() > image.height());
+        Ok(())
+    }
+
+    /// The rows of `image` with anything drawn on them.
+    fn inked_rows(image: &RgbaImage) -> usize {
+        let background = image.get_pixel(0, 0);
+        image
+            .rows()
+            .filter(|row| row.clone().any(|x| x != background))
+            .count()
+    }
+
+    // regression: preformatted lines wider than the image were cut off by
+    // the scrollable they're shown on in the window
+    #[test]
+    fn test_render_wraps_preformatted() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/")?;
+        let line = "x".repeat(40);
+        let short = Document::parse(&url, &format!("```\n{line}\n```\n"));
+        let long = Document::parse(&url, &format!("```\n{}\n```\n", line.repeat(5)));
+
+        let theme = Theme::Light;
+        let short = render_document(&short, 480, &theme)?;
+        let long = render_document(&long, 480, &theme)?;
+        // five times the characters take up about five times the rows,
+        // rather than being cut off at the edge
+        assert!(long.height() > short.height());
+        assert!(inked_rows(&long) >= 4 * inked_rows(&short));
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+use iced::{
+    Element,
+    Length::{Fill, Shrink},
+    widget::{Column, button, checkbox, container},
+};
+
+use crate::settings::Settings;
+
+pub struct SettingsModal {
+    settings: Settings,
+}
+
+impl SettingsModal {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            settings: settings.clone(),
+        }
+    }
+
+    /// `on_change` receives the settings with the toggled option applied.
+    pub fn view<'a, Message: Clone + 'a>(
+        self,
+        on_change: fn(Settings) -> Message,
+        on_close: Message,
+    ) -> Element<'a, Message> {
+        let settings = self.settings;
+
+        let options = Column::new()
+            .push(
+                checkbox(settings.collapse_preformatted)
+                    .label("Collapse preformatted blocks behind their alt text")
+                    .on_toggle({
+                        let settings = settings.clone();
+                        move |x| {
+                            on_change(Settings {
+                                collapse_preformatted: x,
+                                ..settings.clone()
+                            })
+                        }
+                    }),
+            )
+            .push(
+                checkbox(settings.strip_ansi)
+                    .label("Strip ANSI colours from preformatted text")
+                    .on_toggle({
+                        let settings = settings.clone();
+                        move |x| {
+                            on_change(Settings {
+                                strip_ansi: x,
+                                ..settings.clone()
+                            })
+                        }
+                    }),
+            )
+            .push(
+                checkbox(settings.shrink_preformatted)
+                    .label("Shrink wide preformatted blocks to fit the page")
+                    .on_toggle({
+                        let settings = settings.clone();
+                        move |x| {
+                            on_change(Settings {
+                                shrink_preformatted: x,
+                                ..settings.clone()
+                            })
+                        }
+                    }),
+            )
+            .push(
+                checkbox(settings.inline_images)
+                    .label("Preview images below links to them, on capsules enabled with 🖼")
+                    .on_toggle({
+                        let settings = settings.clone();
+                        move |x| {
+                            on_change(Settings {
+                                inline_images: x,
+                                ..settings.clone()
+                            })
+                        }
+                    }),
+            )
+            .push(
+                checkbox(settings.reduce_motion)
+                    .label("Reduce motion: start animated images paused")
+                    .on_toggle({
+                        let settings = settings.clone();
+                        move |x| {
+                            on_change(Settings {
+                                reduce_motion: x,
+                                ..settings.clone()
+                            })
+                        }
+                    }),
+            )
+            .spacing(10);
+
+        let button_row = container(button("Close").on_press(on_close))
+            .center_x(Fill)
+            .center_y(Shrink)
+            .padding(10);
+
+        Column::new()
+            .push(container(options).padding(10))
+            .push(button_row)
+            .into()
+    }
+}
@@ -0,0 +1,351 @@
+use std::{fmt, io, path::PathBuf};
+
+use iced_gemini::gemini::{
+    Status,
+    client::{Client, FetchOutcome},
+    diff::{self, Change},
+    gemtext::{Document, Line},
+};
+use thiserror::Error;
+use url::Url;
+
+use crate::{
+    config::{self, ConfigFile, LineError},
+    feeds,
+};
+
+// what the lines of `watches` look like
+const FORMAT: &str = "`watch <url> <changed|unchanged>`";
+
+#[derive(Error, Debug)]
+pub enum WatchesError {
+    #[error("page request failed: {0:?}: {1:?}")]
+    Status(Status, Option<String>),
+    #[error("not a page: {0}")]
+    NotAPage(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watch {
+    pub url: Url,
+    pub changed: bool, // since it was last opened
+}
+
+/// Pages watched for changes, stored in `watches` in the config directory.
+/// The version of each page last seen is kept as gemtext in `snapshots`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Watches {
+    watches: Vec<Watch>,
+}
+
+impl ConfigFile for Watches {
+    const NAME: &'static str = "watches";
+
+    type Error = LineError;
+
+    fn parse_line(&mut self, line_number: usize, line: &str) -> Result<(), LineError> {
+        let malformed = || LineError::MalformedLine(line_number, FORMAT);
+        let mut fields = line.split_whitespace();
+        let (Some("watch"), Some(url), Some(changed), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(malformed());
+        };
+        self.watches.push(Watch {
+            url: config::parse_url(line_number, url)?,
+            changed: match changed {
+                "changed" => true,
+                "unchanged" => false,
+                _ => return Err(malformed()),
+            },
+        });
+        Ok(())
+    }
+}
+
+impl Watches {
+    fn snapshot_path(url: &Url) -> Option<PathBuf> {
+        config::config_dir().map(|dir| dir.join("snapshots").join(snapshot_name(url)))
+    }
+
+    pub fn is_watched(&self, url: &Url) -> bool {
+        self.watches.iter().any(|x| x.url == *url)
+    }
+
+    pub fn urls(&self) -> impl Iterator<Item = &Url> {
+        self.watches.iter().map(|x| &x.url)
+    }
+
+    /// Starts watching the page `doc` was loaded from, taking `doc` as the
+    /// version last seen.
+    pub fn watch(&mut self, doc: &Document) -> anyhow::Result<()> {
+        write_snapshot(doc)?;
+        if !self.is_watched(&doc.url) {
+            self.watches.push(Watch {
+                url: doc.url.clone(),
+                changed: false,
+            });
+        }
+        Ok(())
+    }
+
+    /// Also deletes the page's snapshot.
+    pub fn unwatch(&mut self, url: &Url) -> anyhow::Result<()> {
+        self.watches.retain(|x| x.url != *url);
+        let path = Self::snapshot_path(url).ok_or(anyhow::anyhow!("no config directory"))?;
+        match std::fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Flags the watched page `doc` was loaded from as changed if it differs
+    /// from the version last seen. Returns whether it was newly flagged.
+    pub fn check(&mut self, doc: &Document) -> anyhow::Result<bool> {
+        let Some(watch) = self.watches.iter_mut().find(|x| x.url == doc.url) else {
+            return Ok(false);
+        };
+        if watch.changed {
+            return Ok(false);
+        }
+        let Some(snapshot) = read_snapshot(&doc.url)? else {
+            // nothing to compare against, so start from this version
+            write_snapshot(doc)?;
+            return Ok(false);
+        };
+        // the changes themselves are only worked out when the page is opened
+        watch.changed = doc.to_gemtext() != snapshot;
+        Ok(watch.changed)
+    }
+
+    /// Records that `doc` has been seen, if its page is watched. Returns its
+    /// changes since it was last seen, if there are any.
+    pub fn visit(&mut self, doc: &Document) -> anyhow::Result<Option<Vec<(Change, Line)>>> {
+        let Some(watch) = self.watches.iter_mut().find(|x| x.url == doc.url) else {
+            return Ok(None);
+        };
+        watch.changed = false;
+        let changes = match read_snapshot(&doc.url)? {
+            Some(snapshot) => changes(&snapshot, doc),
+            None => {
+                write_snapshot(doc)?;
+                return Ok(None);
+            }
+        };
+        if changes.is_some() {
+            write_snapshot(doc)?;
+        }
+        Ok(changes)
+    }
+
+    /// The `about:feeds` section listing the watched pages, changed first.
+    pub fn to_lines(&self) -> Vec<Line> {
+        let mut lines = vec![Line::Heading(2, "Watched pages".into())];
+        if self.watches.is_empty() {
+            lines.push(Line::Text(
+                "None yet: press 👁 on a page to be told when it changes.".into(),
+            ));
+        }
+        let (changed, unchanged): (Vec<_>, Vec<_>) = self.watches.iter().partition(|x| x.changed);
+        for watch in changed {
+            lines.push(Line::Link(
+                watch.url.clone(),
+                Some(format!("{} (changed)", watch.url)),
+            ));
+        }
+        for watch in unchanged {
+            lines.push(Line::Link(watch.url.clone(), None));
+        }
+        lines
+    }
+}
+
+impl fmt::Display for Watches {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for watch in &self.watches {
+            let changed = if watch.changed {
+                "changed"
+            } else {
+                "unchanged"
+            };
+            writeln!(f, "watch {} {changed}", watch.url)?;
+        }
+        Ok(())
+    }
+}
+
+fn read_snapshot(url: &Url) -> anyhow::Result<Option<String>> {
+    let path = Watches::snapshot_path(url).ok_or(anyhow::anyhow!("no config directory"))?;
+    match std::fs::read_to_string(path) {
+        Ok(x) => Ok(Some(x)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn write_snapshot(doc: &Document) -> anyhow::Result<()> {
+    let path = Watches::snapshot_path(&doc.url).ok_or(anyhow::anyhow!("no config directory"))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, doc.to_gemtext())?;
+    Ok(())
+}
+
+/// The changes from the gemtext `snapshot` to `doc`, if there are any. Both
+/// sides go through gemtext, so only changes it can express count.
+fn changes(snapshot: &str, doc: &Document) -> Option<Vec<(Change, Line)>> {
+    let gemtext = doc.to_gemtext();
+    if gemtext == snapshot {
+        return None;
+    }
+    let old = Document::parse(&doc.url, snapshot);
+    let new = Document::parse(&doc.url, &gemtext);
+    Some(diff::diff(&old.lines, &new.lines))
+}
+
+/// Fetches the watched pages at `urls`, which should all be on one host, one
+/// at a time. Waits before every request, as the host's feeds are fetched
+/// just before, and for as long as the server asks to if it says to slow
+/// down, blocking the thread it's called on throughout.
+pub fn fetch_all(client: &Client, urls: &[Url]) -> Vec<(Url, Result<Document, String>)> {
+    let mut results = Vec::new();
+    for url in urls {
+        std::thread::sleep(feeds::HOST_INTERVAL);
+        let mut result = fetch(client, url);
+        if let Some(WatchesError::Status(Status::SlowDown, meta)) =
+            result.as_ref().err().and_then(|e| e.downcast_ref())
+        {
+            let wait = feeds::slow_down_wait(meta.as_deref());
+            log::info!("Watches: slowing down for {wait:?} before retrying {url}");
+            std::thread::sleep(wait);
+            result = fetch(client, url);
+        }
+        results.push((url.clone(), result.map_err(|e| e.to_string())));
+    }
+    results
+}
+
+/// Snapshots are named after a hash of the page's URL, which unlike the URL
+/// itself always makes a valid file name.
+fn snapshot_name(url: &Url) -> String {
+    format!("{:032x}.gmi", fnv1a(url.as_str().as_bytes()))
+}
+
+/// The 128-bit FNV-1a hash of `data`, which unlike `std`'s hashers is the
+/// same from one build to the next.
+fn fnv1a(data: &[u8]) -> u128 {
+    const OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+    data.iter().fold(OFFSET_BASIS, |hash, &byte| {
+        (hash ^ u128::from(byte)).wrapping_mul(PRIME)
+    })
+}
+
+fn fetch(client: &Client, url: &Url) -> anyhow::Result<Document> {
+    match client.fetch(url, None)? {
+        // keep the watched URL, even if the page has moved
+        FetchOutcome::Page(_, content) => Ok(Document::from_lines(url, content.document.lines)),
+        FetchOutcome::Unsupported(_, mime, _) => {
+            Err(WatchesError::NotAPage(mime.to_string()).into())
+        }
+        FetchOutcome::Input { .. } => Err(WatchesError::NotAPage("input prompt".into()).into()),
+        FetchOutcome::Failure(_, status, meta) => Err(WatchesError::Status(status, meta).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let (watches, errors) = Watches::parse(
+            "# status boards\nwatch gemini://example.com/status.gmi changed\n\nwatch gemini://example.com/wiki/ unchanged\n",
+        );
+        assert!(errors.is_empty());
+        assert_eq!(
+            watches.watches,
+            [
+                Watch {
+                    url: Url::parse("gemini://example.com/status.gmi")?,
+                    changed: true,
+                },
+                Watch {
+                    url: Url::parse("gemini://example.com/wiki/")?,
+                    changed: false,
+                },
+            ]
+        );
+        assert_eq!(Watches::parse(&watches.to_string()).0, watches);
+
+        // bad lines are skipped, keeping the rest
+        let (parsed, errors) = Watches::parse(
+            "watch gemini://example.com/\n\
+             watch gemini://example.com/ maybe\n\
+             watch example changed\n\
+             watch gemini://example.com/wiki/ unchanged\n",
+        );
+        assert!(matches!(
+            errors[..],
+            [
+                LineError::MalformedLine(1, _),
+                LineError::MalformedLine(2, _),
+                LineError::InvalidUrl(3, _),
+            ]
+        ));
+        assert_eq!(parsed.watches, watches.watches[1..]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_name() -> anyhow::Result<()> {
+        assert_eq!(fnv1a(b"a"), 0xd228cb696f1a8caf78912b704e4a8964);
+
+        let long = Url::parse(&format!("gemini://example.com/{}", "a/".repeat(1000)))?;
+        assert_eq!(snapshot_name(&long).len(), 36);
+        assert_ne!(
+            snapshot_name(&long),
+            snapshot_name(&Url::parse("gemini://example.com/")?)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_changes() -> anyhow::Result<()> {
+        let url = Url::parse("gemini://example.com/status.gmi")?;
+        let doc = Document::parse(&url, "# Status\r\nAll good\r\n");
+        assert_eq!(changes("# Status\nAll good\n", &doc), None);
+
+        let changes = changes("# Status\nDown\n", &doc).expect("the page changed");
+        assert_eq!(
+            changes,
+            [
+                (Change::Unchanged, Line::Heading(1, "Status".into())),
+                (Change::Removed, Line::Text("Down".into())),
+                (Change::Added, Line::Text("All good".into())),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_lines() -> anyhow::Result<()> {
+        let (watches, _) = Watches::parse(
+            "watch gemini://example.com/a unchanged\nwatch gemini://example.com/b changed\n",
+        );
+        let labels: Vec<_> = watches
+            .to_lines()
+            .iter()
+            .filter_map(|line| match line {
+                Line::Link(url, label) => Some(label.clone().unwrap_or(url.to_string())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            labels,
+            ["gemini://example.com/b (changed)", "gemini://example.com/a"]
+        );
+        Ok(())
+    }
+}